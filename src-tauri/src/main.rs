@@ -1,21 +1,25 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs;
+use std::io::Write;
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Instant, SystemTime};
 use tauri::Window;
 
 // ==================== 数据结构 ====================
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct DiskItem {
     name: String,
     path: String,
@@ -26,11 +30,127 @@ struct DiskItem {
     file_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>, // 用于标记权限错误等
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest_files: Option<Vec<LargestFile>>, // 目录内最大的若干文件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_histogram: Option<Vec<HistogramBucket>>, // 目录内文件大小分布
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_buckets: Option<Vec<AgeBucket>>, // 目录内文件按时间分布 (冷热数据)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<u64>, // 目录内最新文件的时间 (Unix 秒)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    is_cache: bool, // 带 CACHEDIR.TAG 或 .nobackup 标记的可再生缓存目录，可安全删除
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    is_protected: bool, // 受 .cleandirignore 保护，不应建议删除 (删除时会被拒绝)
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct LargestFile {
+    name: String,
+    path: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize, Default)]
 struct ScanResult {
     items: Vec<DiskItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest_files: Option<Vec<LargestFile>>, // 整棵树中最大的若干文件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_histogram: Option<Vec<HistogramBucket>>, // 整棵树的文件大小分布
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_buckets: Option<Vec<AgeBucket>>, // 整棵树的文件时间分布
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root_totals: Option<Vec<RootTotal>>, // 多根目录扫描时每个根目录的汇总
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>, // 不影响结果但需要提醒用户的问题
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct HistogramBucket {
+    min_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_size: Option<u64>, // 不含上界，最后一档为 None
+    count: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AgeBucket {
+    label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_age_days: Option<u64>, // 不含上界，最后一档为 None
+    count: u64,
+    bytes: u64,
+}
+
+// 按哪个时间戳计算文件年龄
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum TimeField {
+    #[default]
+    Modified, // mtime: 内容最后修改
+    Accessed, // atime: 最后读取 (受挂载选项影响)
+    Changed,  // ctime: 元数据最后变更
+    Created,  // birth time: 创建时间 (Linux 通过 statx 获取)
+}
+
+// 旧文件扫描的统计单位
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum OldFilesMode {
+    #[default]
+    Files, // 逐个文件判断
+    Directories, // 整个目录内最新文件都超过阈值才算陈旧
+}
+
+// 重复文件组中的单个文件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DuplicateFile {
+    name: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>, // Unix 秒
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hard_links: Vec<String>, // 指向同一 inode 的其他路径
+    #[serde(default)]
+    already_linked: bool, // inode 有多个硬链接，删除这个路径不一定释放空间
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    is_reference: bool, // 位于参考目录，永远不会被建议删除
+}
+
+impl DuplicateFile {
+    // 这个成员对应的全部路径 (含硬链接)
+    fn all_paths(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.path).chain(self.hard_links.iter())
+    }
+}
+
+// 一组内容完全相同的文件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DuplicateGroup {
+    hash: String, // 全量内容 BLAKE3 哈希
+    size: u64,    // 单个文件大小
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_type: Option<String>,
+    files: Vec<DuplicateFile>, // 每个 inode 一个成员
+    reclaimable_bytes: u64,    // 只保留一份时可释放的空间: size × (inode 数 - 1)
+}
+
+// 多目录重复扫描的目录
+#[derive(Debug, Deserialize, Clone)]
+struct DuplicateRoot {
+    path: String,
+    #[serde(default)]
+    reference: bool, // 参考目录: 只用来比对，其中的文件不会被建议删除
+}
+
+#[derive(Debug, Serialize, Default)]
+struct DuplicateScanResult {
+    groups: Vec<DuplicateGroup>,
+    total_reclaimable_bytes: u64,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -111,6 +231,27 @@ fn should_skip_path(path: &Path, enable_smart_filter: bool) -> bool {
     SKIP_PATTERNS.iter().any(|pattern| pattern.is_match(&path_str))
 }
 
+// Cache Directory Tagging 规范: CACHEDIR.TAG 必须以该签名开头
+// https://bford.info/cachedir/
+const CACHEDIR_TAG_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+// 检查目录是否被标记为可再生缓存 (CACHEDIR.TAG 或 .nobackup)
+fn is_tagged_cache_dir(dir: &Path) -> bool {
+    use std::io::Read;
+
+    if dir.join(".nobackup").exists() {
+        return true;
+    }
+
+    let mut signature = [0u8; CACHEDIR_TAG_SIGNATURE.len()];
+    match fs::File::open(dir.join("CACHEDIR.TAG")) {
+        Ok(mut file) => {
+            file.read_exact(&mut signature).is_ok() && signature == CACHEDIR_TAG_SIGNATURE
+        }
+        Err(_) => false,
+    }
+}
+
 // 获取目录优先级权重 (数值越大越优先)
 fn get_dir_priority(path: &Path) -> u8 {
     let name = path.file_name().unwrap_or_default().to_string_lossy();
@@ -120,6 +261,184 @@ fn get_dir_priority(path: &Path) -> u8 {
     0 // 普通优先级
 }
 
+// ==================== .cleandirignore 保护规则 ====================
+
+// 扫描树内的 .cleandirignore 使用 gitignore 语法，命中的路径都不允许删除
+// 默认段落中的条目受保护: 照常统计占用，但不会被建议清理；[skip] 段落之后的条目扫描时整体跳过
+const IGNORE_FILE_NAME: &str = ".cleandirignore";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IgnoreAction {
+    Protect,
+    Skip,
+}
+
+// 单个目录下解析好的 .cleandirignore
+struct IgnoreFile {
+    protect: Gitignore,
+    skip: Gitignore,
+}
+
+impl IgnoreFile {
+    fn load(dir: &Path) -> Option<Arc<IgnoreFile>> {
+        let file = dir.join(IGNORE_FILE_NAME);
+        let content = fs::read_to_string(&file).ok()?;
+
+        let mut protect = GitignoreBuilder::new(dir);
+        let mut skip = GitignoreBuilder::new(dir);
+        let mut action = IgnoreAction::Protect;
+        for line in content.lines() {
+            match line.trim() {
+                "[protect]" => action = IgnoreAction::Protect,
+                "[skip]" => action = IgnoreAction::Skip,
+                _ => {
+                    let builder = match action {
+                        IgnoreAction::Protect => &mut protect,
+                        IgnoreAction::Skip => &mut skip,
+                    };
+                    if let Err(e) = builder.add_line(Some(file.clone()), line) {
+                        println!("警告：{} 解析失败: {}", file.display(), e);
+                    }
+                }
+            }
+        }
+
+        Some(Arc::new(IgnoreFile {
+            protect: protect.build().unwrap_or_else(|_| Gitignore::empty()),
+            skip: skip.build().unwrap_or_else(|_| Gitignore::empty()),
+        }))
+    }
+}
+
+// 当前路径上各级目录的规则 (由外到内)，每次遍历各自持有，不需要加锁
+// 只加载遍历起点及以下目录中的规则，扫描根目录之外的 .cleandirignore 不生效
+#[derive(Clone, Default)]
+struct IgnoreStack {
+    files: Vec<(usize, Option<Arc<IgnoreFile>>)>, // (层级, 该层目录的规则)
+}
+
+impl IgnoreStack {
+    // 删除时没有扫描根目录: 以 path 各级上层目录的规则为起点，用于从 path 开始的遍历
+    fn for_ancestors(path: &Path) -> Self {
+        let mut ancestors: Vec<&Path> = path.ancestors().skip(1).collect();
+        ancestors.reverse();
+        IgnoreStack {
+            files: ancestors
+                .into_iter()
+                .map(|dir| (0, IgnoreFile::load(dir)))
+                .collect(),
+        }
+    }
+
+    // 以 dir 自身的规则为起点，用于从 dir 的子目录开始的遍历
+    fn for_dir(dir: &Path) -> Self {
+        IgnoreStack {
+            files: vec![(0, IgnoreFile::load(dir))],
+        }
+    }
+
+    // 手动递归时进入子目录 dir
+    fn with_dir(&self, dir: &Path) -> Self {
+        let mut stack = self.clone();
+        stack.files.push((stack.files.len(), IgnoreFile::load(dir)));
+        stack
+    }
+
+    // 越近的规则优先，支持 ! 取反；同一文件内跳过规则优先于保护规则
+    fn action(&self, path: &Path, is_dir: bool) -> Option<IgnoreAction> {
+        for file in self
+            .files
+            .iter()
+            .rev()
+            .filter_map(|(_, file)| file.as_ref())
+        {
+            for (matcher, action) in [
+                (&file.skip, IgnoreAction::Skip),
+                (&file.protect, IgnoreAction::Protect),
+            ] {
+                match matcher.matched_path_or_any_parents(path, is_dir) {
+                    Match::Ignore(_) => return Some(action),
+                    Match::Whitelist(_) => return None,
+                    Match::None => {}
+                }
+            }
+        }
+        None
+    }
+
+    // WalkDir 按深度优先顺序产出条目: 先弹出已离开的目录的规则，
+    // 再在第一次遇到某目录的子项时加载该目录的规则，遍历起点本身总是保留
+    fn walk_action(&mut self, entry: &walkdir::DirEntry) -> Option<IgnoreAction> {
+        let depth = entry.depth();
+        while self.files.last().is_some_and(|(level, _)| *level > depth) {
+            self.files.pop();
+        }
+        if depth == 0 {
+            return None;
+        }
+        if self.files.last().is_none_or(|(level, _)| *level < depth) {
+            let parent = entry.path().parent().unwrap_or(Path::new(""));
+            self.files.push((depth, IgnoreFile::load(parent)));
+        }
+        self.action(entry.path(), entry.file_type().is_dir())
+    }
+
+    // 用于清理类扫描的 WalkDir::filter_entry: 受保护和跳过的条目都不作为候选
+    fn allows(&mut self, entry: &walkdir::DirEntry) -> bool {
+        self.walk_action(entry).is_none()
+    }
+
+    // 用于空间统计的 WalkDir::filter_entry: 受保护的条目照常统计
+    fn allows_stats(&mut self, entry: &walkdir::DirEntry) -> bool {
+        self.walk_action(entry) != Some(IgnoreAction::Skip)
+    }
+
+    // 既要汇总目录、又要给出清理候选时使用: 受保护的条目照常计入汇总，
+    // 同时记入 protected，由调用方从候选中排除
+    fn allows_tracking(
+        &mut self,
+        entry: &walkdir::DirEntry,
+        protected: &mut HashSet<PathBuf>,
+    ) -> bool {
+        match self.walk_action(entry) {
+            Some(IgnoreAction::Skip) => false,
+            Some(IgnoreAction::Protect) => {
+                protected.insert(entry.path().to_path_buf());
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+// 删除前检查: 路径本身或目录内任一条目受保护时返回原因
+// 上层目录和目录内各级的 .cleandirignore 都生效，遇到第一个受保护的条目即停止
+fn deletion_blocked(path: &Path) -> Option<String> {
+    use walkdir::WalkDir;
+
+    let mut ignore_rules = IgnoreStack::for_ancestors(path);
+    let is_dir = path.symlink_metadata().map(|m| m.is_dir()).unwrap_or(false);
+    if ignore_rules.action(path, is_dir).is_some() {
+        return Some(format!("受 {} 保护，不能删除", IGNORE_FILE_NAME));
+    }
+    if !is_dir {
+        return None;
+    }
+
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| ignore_rules.walk_action(e).is_some())
+        .map(|e| {
+            format!(
+                "包含受 {} 保护的 {}，不能删除",
+                IGNORE_FILE_NAME,
+                e.path().display()
+            )
+        })
+}
+
 // ==================== 动态线程池配置 ====================
 
 fn get_optimal_thread_count() -> usize {
@@ -145,690 +464,4608 @@ fn init_rayon_pool() {
         .ok(); // 忽略重复初始化错误
 }
 
+// ==================== 文件属主 ====================
+
+// 通过 uid 查询用户名 (查不到时返回 None)
+fn user_name(uid: u32) -> Option<String> {
+    use std::ffi::CStr;
+
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 4096];
+
+    let ret = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() {
+        return None;
+    }
+
+    let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().to_string())
+}
+
+// 扫描期间缓存 uid -> 用户名，避免重复查询 (查不到用户名时显示 uid)
+#[derive(Default)]
+struct OwnerCache {
+    names: HashMap<u32, String>,
+}
+
+impl OwnerCache {
+    fn get(&mut self, uid: u32) -> String {
+        self.names
+            .entry(uid)
+            .or_insert_with(|| user_name(uid).unwrap_or_else(|| uid.to_string()))
+            .clone()
+    }
+}
+
 // ==================== 硬链接去重 (inode 追踪) ====================
 
-type InodeSet = Arc<Mutex<HashSet<u64>>>;
+// inode 只在同一设备内唯一，跨文件系统扫描时需要连同设备号一起记录
+type InodeSet = Arc<Mutex<HashSet<(u64, u64)>>>;
 
-fn is_duplicate_inode(inode: u64, seen_inodes: &InodeSet) -> bool {
+fn is_duplicate_inode(dev: u64, inode: u64, seen_inodes: &InodeSet) -> bool {
     let mut set = seen_inodes.lock().unwrap();
-    !set.insert(inode) // 如果已存在,返回 true
+    !set.insert((dev, inode)) // 如果已存在,返回 true
 }
 
-// ==================== 扫描核心逻辑 ====================
+// ==================== 最大文件追踪 (有界小顶堆) ====================
 
-// 使用 walkdir 计算目录大小 (支持智能过滤、硬链接去重)
-// 增加进度回调支持
-fn calculate_dir_size_walkdir(
-    path: &Path,
-    enable_smart_filter: bool,
-    seen_inodes: &InodeSet,
-) -> u64 {
-    use walkdir::WalkDir;
+const DEFAULT_TOP_N: usize = 10;
 
-    WalkDir::new(path)
-        .follow_links(false)
-        .max_depth(10) // 限制递归深度，避免无限深入
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            // 应用智能过滤
-            !should_skip_path(e.path(), enable_smart_filter)
-        })
-        .filter(|e| e.file_type().is_file())
-        .filter_map(|e| e.metadata().ok())
-        .filter_map(|m| {
-            // 硬链接去重
-            let inode = m.ino();
-            if is_duplicate_inode(inode, seen_inodes) {
-                None // 跳过重复的 inode
-            } else {
-                Some(m.blocks() * 512) // 返回实际磁盘占用
-            }
-        })
-        .sum()
+// 只保留最大的 limit 个文件，堆顶为当前最小值，便于淘汰
+struct TopFiles {
+    limit: usize,
+    heap: BinaryHeap<Reverse<(u64, PathBuf)>>,
 }
 
-// ==================== 快速扫描 (两阶段优化版) ====================
+impl TopFiles {
+    fn new(limit: usize) -> Self {
+        TopFiles {
+            limit,
+            heap: BinaryHeap::with_capacity(limit + 1),
+        }
+    }
 
-#[tauri::command]
-async fn scan_directory_fast(
-    path: String,
-    window: Window,
-    enable_smart_filter: Option<bool>,
-) -> Result<ScanResult, String> {
-    use rayon::prelude::*;
+    fn push(&mut self, size: u64, path: &Path) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.heap.len() == self.limit {
+            // 比堆中最小的还小，直接丢弃，避免无谓的路径拷贝
+            match self.heap.peek() {
+                Some(Reverse((min_size, _))) if size <= *min_size => return,
+                _ => {}
+            }
+        }
+        self.heap.push(Reverse((size, path.to_path_buf())));
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
 
-    // 初始化线程池
-    init_rayon_pool();
+    fn merge(&mut self, other: &TopFiles) {
+        for Reverse((size, path)) in other.heap.iter() {
+            self.push(*size, path);
+        }
+    }
 
-    let enable_filter = enable_smart_filter.unwrap_or(true);
-    let path_obj = Path::new(&path);
+    // 按大小降序输出
+    fn to_vec(&self) -> Vec<LargestFile> {
+        let mut files: Vec<LargestFile> = self
+            .heap
+            .iter()
+            .map(|Reverse((size, path))| LargestFile {
+                name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                path: path.to_string_lossy().to_string(),
+                size: *size,
+            })
+            .collect();
+        files.sort_by_key(|f| Reverse(f.size));
+        files
+    }
+}
 
-    // 读取目录内容
-    let entries: Vec<_> = match fs::read_dir(path_obj) {
-        Ok(entries) => entries.filter_map(|e| e.ok()).collect(),
-        Err(e) => return Err(format!("读取目录失败: {}", e)),
-    };
+// ==================== 文件大小分布 (对数刻度直方图) ====================
 
-    let total = entries.len();
-    let start_time = SystemTime::now();
+// 分档上界: 1KB 起每档 ×4，超过 4GB 归入最后一档
+const HISTOGRAM_BOUNDS: [u64; 12] = [
+    1 << 10,
+    4 << 10,
+    16 << 10,
+    64 << 10,
+    256 << 10,
+    1 << 20,
+    4 << 20,
+    16 << 20,
+    64 << 20,
+    256 << 20,
+    1 << 30,
+    4 << 30,
+];
+const HISTOGRAM_BUCKETS: usize = HISTOGRAM_BOUNDS.len() + 1;
 
-    // 发送初始进度
-    let _ = window.emit(
-        "scan-progress",
-        ScanProgress {
-            percent: 5,
-            current: 0,
-            total,
-            current_item: "正在读取目录列表...".to_string(),
-            elapsed_seconds: 0,
-            estimated_remaining_seconds: 0,
-        },
-    );
+// 按文件逻辑大小分档，bytes 累计实际磁盘占用 (与 size 字段口径一致)
+#[derive(Clone, Default)]
+struct SizeHistogram {
+    counts: [u64; HISTOGRAM_BUCKETS],
+    bytes: [u64; HISTOGRAM_BUCKETS],
+}
 
-    // 第一阶段: 快速收集基本信息
-    let mut items: Vec<DiskItem> = entries
-        .iter()
-        .filter_map(|entry| {
-            let entry_path = entry.path();
-            let name = entry.file_name().to_string_lossy().to_string();
+impl SizeHistogram {
+    fn add(&mut self, file_len: u64, disk_size: u64) {
+        let index = HISTOGRAM_BOUNDS
+            .iter()
+            .position(|&bound| file_len < bound)
+            .unwrap_or(HISTOGRAM_BOUNDS.len());
+        self.counts[index] += 1;
+        self.bytes[index] += disk_size;
+    }
 
-            // 跳过隐藏文件
-            if name.starts_with('.') {
-                return None;
-            }
+    fn merge(&mut self, other: &SizeHistogram) {
+        for i in 0..HISTOGRAM_BUCKETS {
+            self.counts[i] += other.counts[i];
+            self.bytes[i] += other.bytes[i];
+        }
+    }
 
-            // 智能过滤
-            if should_skip_path(&entry_path, enable_filter) {
-                return None;
-            }
+    fn file_count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
 
-            // 获取元数据
-            let metadata = match entry_path.metadata() {
-                Ok(m) => m,
-                Err(e) => {
-                    let error_type = ErrorType::from(&e);
-                    let error_msg = match error_type {
-                        ErrorType::PermissionDenied => "无权限访问",
-                        ErrorType::NotFound => "文件不存在",
-                        ErrorType::IOError => "磁盘 I/O 错误",
-                    };
-                    return Some(DiskItem {
-                        name,
-                        path: entry_path.to_string_lossy().to_string(),
-                        size: 0,
-                        is_directory: false,
-                        item_count: 0,
-                        file_type: None,
-                        error: Some(error_msg.to_string()),
-                    });
-                }
-            };
+    fn to_buckets(&self) -> Vec<HistogramBucket> {
+        (0..HISTOGRAM_BUCKETS)
+            .map(|i| HistogramBucket {
+                min_size: if i == 0 { 0 } else { HISTOGRAM_BOUNDS[i - 1] },
+                max_size: HISTOGRAM_BOUNDS.get(i).copied(),
+                count: self.counts[i],
+                bytes: self.bytes[i],
+            })
+            .collect()
+    }
+}
 
-            let is_directory = metadata.is_dir();
-            let file_type = if is_directory {
-                Some("directory".to_string())
-            } else {
-                get_file_type(&name)
-            };
+// ==================== 文件年龄分布 (冷热数据) ====================
 
-            // 文件直接获取大小，目录先设为 0
-            let size = if is_directory {
-                0
-            } else {
-                metadata.blocks() * 512
-            };
+// 分档上界 (天): 1 周、1 个月、半年、1 年、3 年，更早的归入最后一档
+const AGE_BUCKET_DAYS: [(u64, &str); 5] = [
+    (7, "1w"),
+    (30, "1m"),
+    (182, "6m"),
+    (365, "1y"),
+    (3 * 365, "3y"),
+];
+const AGE_BUCKETS: usize = AGE_BUCKET_DAYS.len() + 1;
 
-            Some(DiskItem {
-                name,
-                path: entry_path.to_string_lossy().to_string(),
-                size,
-                is_directory,
-                item_count: 0,
-                file_type,
-                error: None,
-            })
-        })
-        .collect();
+fn file_time(metadata: &fs::Metadata, field: TimeField) -> Option<SystemTime> {
+    use std::time::{Duration, UNIX_EPOCH};
 
-    // 发送 20% 进度
-    let _ = window.emit(
-        "scan-progress",
-        ScanProgress {
-            percent: 20,
-            current: items.len(),
-            total: items.len(),
-            current_item: "正在计算目录大小...".to_string(),
-            elapsed_seconds: start_time.elapsed().unwrap_or_default().as_secs(),
-            estimated_remaining_seconds: 0,
-        },
-    );
+    match field {
+        TimeField::Modified => metadata.modified().ok(),
+        TimeField::Accessed => metadata.accessed().ok(),
+        TimeField::Changed => {
+            let secs = u64::try_from(metadata.ctime()).ok()?;
+            Some(UNIX_EPOCH + Duration::new(secs, metadata.ctime_nsec() as u32))
+        }
+        // 文件系统不支持时返回 None
+        TimeField::Created => metadata.created().ok(),
+    }
+}
 
-    // 第二阶段: 并行计算目录大小
-    let dirs_count = items.iter().filter(|i| i.is_directory).count();
-    let processed_dirs = Arc::new(AtomicUsize::new(0));
-    let seen_inodes: InodeSet = Arc::new(Mutex::new(HashSet::new()));
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
 
-    // 并行计算每个目录的大小
-    let dir_sizes: Vec<(String, u64)> = items
-        .par_iter()
-        .filter(|item| item.is_directory)
-        .map(|item| {
-            let path = Path::new(&item.path);
-            let size = calculate_dir_size_walkdir(path, enable_filter, &seen_inodes);
-            
-            // 更新进度
-            let curr = processed_dirs.fetch_add(1, Ordering::Relaxed) + 1;
-            let base_percent = 20;
-            let progress_percent = base_percent + ((curr as f64 / dirs_count.max(1) as f64) * 75.0) as u8;
-            
-            let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
-            let speed = if elapsed > 0 { curr as f64 / elapsed as f64 } else { 0.0 };
-            let remaining = if speed > 0.0 && dirs_count > curr {
-                ((dirs_count - curr) as f64 / speed) as u64
-            } else {
-                0
-            };
+// ==================== 目录聚合 ====================
 
-            let _ = window.emit(
-                "scan-progress",
-                ScanProgress {
-                    percent: progress_percent.min(95),
-                    current: curr,
-                    total: dirs_count,
-                    current_item: item.name.clone(),
-                    elapsed_seconds: elapsed,
-                    estimated_remaining_seconds: remaining,
-                },
-            );
+// 目录的累计结果 (包含所有子孙文件)
+#[derive(Debug, Default, Clone)]
+struct DirAggregate {
+    size: u64,
+    file_count: usize,
+    newest: Option<SystemTime>,
+}
 
-            (item.path.clone(), size)
-        })
-        .collect();
+// 将每个文件的大小和时间累加到 root 以下 (含 root) 的每一级祖先目录
+fn aggregate_to_ancestors<'a, I>(root: &Path, files: I) -> HashMap<PathBuf, DirAggregate>
+where
+    I: IntoIterator<Item = (&'a Path, u64, Option<SystemTime>)>,
+{
+    let mut dirs: HashMap<PathBuf, DirAggregate> = HashMap::new();
 
-    // 更新目录大小
-    let size_map: HashMap<String, u64> = dir_sizes.into_iter().collect();
-    for item in &mut items {
-        if item.is_directory {
-            if let Some(&size) = size_map.get(&item.path) {
-                item.size = size;
+    for (file_path, size, time) in files {
+        let parent = match file_path.parent() {
+            Some(p) => p,
+            None => continue,
+        };
+        for dir in parent.ancestors() {
+            if !dir.starts_with(root) {
+                break;
+            }
+            let aggregate = dirs.entry(dir.to_path_buf()).or_default();
+            aggregate.size += size;
+            aggregate.file_count += 1;
+            if let Some(time) = time {
+                aggregate.newest = Some(aggregate.newest.map_or(time, |t| t.max(time)));
             }
         }
     }
 
-    // 按优先级和大小排序
-    items.sort_by(|a, b| {
-        let a_priority = get_dir_priority(Path::new(&a.path));
-        let b_priority = get_dir_priority(Path::new(&b.path));
-        if a_priority != b_priority {
-            b_priority.cmp(&a_priority)
-        } else {
-            b.size.cmp(&a.size)
-        }
-    });
+    dirs
+}
 
-    // 发送 100% 完成信号
-    let _ = window.emit(
-        "scan-progress",
-        ScanProgress {
-            percent: 100,
-            current: dirs_count,
-            total: dirs_count,
-            current_item: "完成".to_string(),
-            elapsed_seconds: start_time.elapsed().unwrap_or_default().as_secs(),
-            estimated_remaining_seconds: 0,
-        },
-    );
+// 找出陈旧目录: 目录内最新文件的年龄超过阈值
+// 父目录已陈旧时只报告父目录，root 本身不报告
+fn collect_stale_directories(
+    root: &Path,
+    dirs: &HashMap<PathBuf, DirAggregate>,
+    now: SystemTime,
+    threshold: std::time::Duration,
+) -> Vec<DiskItem> {
+    let is_stale = |dir: &Path| -> bool {
+        match dirs.get(dir).and_then(|a| a.newest) {
+            Some(newest) => now
+                .duration_since(newest)
+                .map(|age| age >= threshold)
+                .unwrap_or(false),
+            None => false,
+        }
+    };
 
-    Ok(ScanResult { items })
+    dirs.iter()
+        .filter(|(dir, _)| dir.as_path() != root && is_stale(dir))
+        .filter(|(dir, _)| match dir.parent() {
+            Some(parent) => parent == root || !is_stale(parent),
+            None => true,
+        })
+        .map(|(dir, aggregate)| DiskItem {
+            name: dir
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path: dir.to_string_lossy().to_string(),
+            size: aggregate.size,
+            is_directory: true,
+            item_count: aggregate.file_count,
+            file_type: Some("directory".to_string()),
+            error: None,
+            last_modified: aggregate.newest.map(unix_seconds),
+            is_cache: is_tagged_cache_dir(dir),
+            ..Default::default()
+        })
+        .collect()
 }
 
-// ==================== 文件类型识别 ====================
-
-fn get_file_type(filename: &str) -> Option<String> {
-    let extension = Path::new(filename)
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("");
+// 找出累计大小超过阈值的目录，嵌套命中时只保留最深的一层
+// root 本身不报告
+fn collect_large_directories(
+    root: &Path,
+    dirs: &HashMap<PathBuf, DirAggregate>,
+    threshold: u64,
+) -> Vec<DiskItem> {
+    let large: Vec<&PathBuf> = dirs
+        .iter()
+        .filter(|(dir, aggregate)| dir.as_path() != root && aggregate.size >= threshold)
+        .map(|(dir, _)| dir)
+        .collect();
 
-    match extension.to_lowercase().as_str() {
-        "mp4" | "mov" | "avi" | "mkv" | "wmv" | "flv" | "webm" => Some("video".to_string()),
-        "mp3" | "wav" | "flac" | "aac" | "ogg" | "m4a" => Some("audio".to_string()),
-        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" | "webp" | "heic" => {
-            Some("image".to_string())
-        }
-        "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "txt" | "rtf" => {
-            Some("document".to_string())
+    // 有命中子目录的祖先目录不再单独报告
+    let mut has_large_child: HashSet<&Path> = HashSet::new();
+    for dir in &large {
+        if let Some(parent) = dir.parent() {
+            for ancestor in parent.ancestors() {
+                if !has_large_child.insert(ancestor) {
+                    break; // 上层祖先已被标记过
+                }
+            }
         }
-        "zip" | "rar" | "7z" | "tar" | "gz" | "bz2" | "xz" => Some("archive".to_string()),
-        "dmg" | "pkg" | "app" | "exe" => Some("application".to_string()),
-        "log" | "tmp" | "cache" => Some("cache".to_string()),
-        _ => Some("other".to_string()),
     }
-}
-
-// ==================== 完整扫描 (与快速扫描相同) ====================
 
-#[tauri::command]
-async fn scan_directory(
-    path: String,
-    window: Window,
-    enable_smart_filter: Option<bool>,
-) -> Result<ScanResult, String> {
-    scan_directory_fast(path, window, enable_smart_filter).await
+    large
+        .into_iter()
+        .filter(|dir| !has_large_child.contains(dir.as_path()))
+        .map(|dir| {
+            let aggregate = &dirs[dir];
+            DiskItem {
+                name: dir
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                path: dir.to_string_lossy().to_string(),
+                size: aggregate.size,
+                is_directory: true,
+                item_count: aggregate.file_count,
+                file_type: Some("directory".to_string()),
+                error: None,
+                is_cache: is_tagged_cache_dir(dir),
+                ..Default::default()
+            }
+        })
+        .collect()
 }
 
-// ==================== 安全删除 (优化版：移除预处理，直接删除) ====================
+// ==================== 挂载选项检测 ====================
 
-#[tauri::command]
-async fn delete_items(window: Window, paths: Vec<String>) -> Result<String, String> {
-    use std::process::Command;
-    use std::time::{Duration, Instant};
+// 查找路径所在挂载点的挂载选项
+#[cfg(target_os = "linux")]
+fn mount_options_for(path: &Path) -> Option<Vec<String>> {
+    let path = path.canonicalize().ok()?;
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
 
-    let mut deleted = Vec::new();
-    let mut errors = Vec::new();
-    let total = paths.len();
+    // 取最长匹配的挂载点
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            // 挂载点中的空格等字符以八进制转义 (\040)
+            let mount_point = fields[1].replace("\\040", " ").replace("\\011", "\t");
+            if path.starts_with(&mount_point) {
+                Some((mount_point.len(), fields[3]))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, options)| options.split(',').map(|o| o.to_string()).collect())
+}
 
-    for (index, path) in paths.iter().enumerate() {
-        let path_obj = Path::new(&path);
+#[cfg(target_os = "macos")]
+fn mount_options_for(path: &Path) -> Option<Vec<String>> {
+    use std::process::Command;
 
-        // 发送进度更新
-        let percent = ((index as f64 / total as f64) * 100.0) as u8;
-        let _ = window.emit("delete-progress", serde_json::json!({
-            "percent": percent,
-            "current": index + 1,
-            "total": total,
-            "current_item": Path::new(&path).file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("未知文件")
-                .to_string()
-        }));
+    let path = path.canonicalize().ok()?;
+    let output = Command::new("mount").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
 
-        if !path_obj.exists() {
-            errors.push(format!("{}: 文件不存在", path));
-            continue;
-        }
+    // 格式: /dev/disk3s1 on /System/Volumes/Data (apfs, local, journaled, noatime)
+    text.lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" on ")?;
+            let (mount_point, options) = rest.rsplit_once(" (")?;
+            if path.starts_with(mount_point) {
+                let options = options.trim_end_matches(')');
+                Some((mount_point.len(), options.to_string()))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, options)| options.split(", ").map(|o| o.to_string()).collect())
+}
 
-        // 🚀 关键优化：直接删除，不要预先统计！
-        // 移除耗时的 calculate_dir_size_walkdir 和 walkdir count 操作
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn mount_options_for(_path: &Path) -> Option<Vec<String>> {
+    None
+}
 
-        let start_time = Instant::now();
+// atime 在 noatime / relatime 挂载下不可靠，返回提示文案
+fn atime_reliability_warning(path: &Path) -> Option<String> {
+    let options = mount_options_for(path)?;
+    let option = options
+        .iter()
+        .find(|o| matches!(o.as_str(), "noatime" | "relatime"))?;
+    Some(format!(
+        "该目录所在文件系统以 {} 方式挂载，访问时间 (atime) 可能不准确",
+        option
+    ))
+}
 
-        // 执行删除命令
-        let result = Command::new("osascript")
-            .arg("-e")
-            .arg(format!(
-                "tell application \"Finder\" to delete POSIX file \"{}\"",
-                path
-            ))
-            .output();
+#[derive(Clone, Default)]
+struct AgeHistogram {
+    counts: [u64; AGE_BUCKETS],
+    bytes: [u64; AGE_BUCKETS],
+}
 
-        match result {
-            Ok(output) => {
-                if output.status.success() {
-                    deleted.push(path.clone());
-                } else {
-                    let error_msg = String::from_utf8_lossy(&output.stderr);
-                    errors.push(format!("{}: {}", path, error_msg));
-                }
-            }
-            Err(e) => {
-                // 如果命令执行失败，可能是权限问题或其他系统错误
-                errors.push(format!("{}: 删除失败 - {}", path, e));
-            }
-        }
+impl AgeHistogram {
+    fn add(&mut self, time: SystemTime, now: SystemTime, disk_size: u64) {
+        // 时间在未来 (时钟偏差) 的文件按最新处理
+        let age_days = now.duration_since(time).unwrap_or_default().as_secs() / 86400;
+        let index = AGE_BUCKET_DAYS
+            .iter()
+            .position(|&(days, _)| age_days < days)
+            .unwrap_or(AGE_BUCKET_DAYS.len());
+        self.counts[index] += 1;
+        self.bytes[index] += disk_size;
+    }
 
-        // 检查是否超时（虽然不太可能，但作为安全措施）
-        if start_time.elapsed() > Duration::from_secs(60) {
-            println!("警告：删除 {} 耗时过长 ({}s)", path, start_time.elapsed().as_secs());
+    fn merge(&mut self, other: &AgeHistogram) {
+        for i in 0..AGE_BUCKETS {
+            self.counts[i] += other.counts[i];
+            self.bytes[i] += other.bytes[i];
         }
     }
 
-    // 发送完成信号
-    let _ = window.emit("delete-progress", serde_json::json!({
-        "percent": 100,
-        "current": total,
-        "total": total,
-        "current_item": "完成"
-    }));
-
-    // 返回删除结果摘要
-    if errors.is_empty() {
-        Ok(format!("成功删除 {} 项", deleted.len()))
-    } else {
-        Err(format!(
-            "成功: {}, 失败: {}\n错误详情:\n{}",
-            deleted.len(),
-            errors.len(),
-            errors.join("\n")
-        ))
+    fn to_buckets(&self) -> Vec<AgeBucket> {
+        (0..AGE_BUCKETS)
+            .map(|i| match AGE_BUCKET_DAYS.get(i) {
+                Some(&(days, label)) => AgeBucket {
+                    label: label.to_string(),
+                    max_age_days: Some(days),
+                    count: self.counts[i],
+                    bytes: self.bytes[i],
+                },
+                None => AgeBucket {
+                    label: "older".to_string(),
+                    max_age_days: None,
+                    count: self.counts[i],
+                    bytes: self.bytes[i],
+                },
+            })
+            .collect()
     }
 }
 
-// ==================== 权限检测 ====================
+// ==================== 扫描核心逻辑 ====================
 
-#[tauri::command]
-fn check_disk_access_permission() -> Result<bool, String> {
-    // 尝试访问一个受保护的目录
-    let test_path = Path::new("/Library/Application Support/com.apple.TCC");
+// 单个目录的统计结果
+struct DirStats {
+    size: u64,
+    largest_files: TopFiles,
+    histogram: SizeHistogram,
+    ages: AgeHistogram,
+}
 
-    match fs::read_dir(test_path) {
-        Ok(_) => Ok(true),
-        Err(_) => Ok(false),
+impl DirStats {
+    fn new(options: &DirStatsOptions) -> Self {
+        DirStats {
+            size: 0,
+            largest_files: TopFiles::new(options.top_n),
+            histogram: SizeHistogram::default(),
+            ages: AgeHistogram::default(),
+        }
     }
-}
 
-// ==================== 获取用户主目录 ====================
+    fn add_file(
+        &mut self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        options: &DirStatsOptions,
+        seen_inodes: &InodeSet,
+    ) {
+        // 硬链接去重
+        if is_duplicate_inode(metadata.dev(), metadata.ino(), seen_inodes) {
+            return; // 跳过重复的 inode
+        }
 
-#[tauri::command]
-fn get_home_dir() -> Result<String, String> {
-    match dirs::home_dir() {
-        Some(path) => Ok(path.to_string_lossy().to_string()),
-        None => Err("无法获取用户主目录".to_string()),
+        let size = metadata.blocks() * 512; // 实际磁盘占用
+        self.size += size;
+        self.largest_files.push(size, path);
+        self.histogram.add(metadata.len(), size);
+        if let Some(time) = file_time(metadata, options.age_field) {
+            self.ages.add(time, options.now, size);
+        }
+    }
+
+    fn merge(&mut self, other: &DirStats) {
+        self.size += other.size;
+        self.largest_files.merge(&other.largest_files);
+        self.histogram.merge(&other.histogram);
+        self.ages.merge(&other.ages);
     }
 }
 
-// ==================== 专项扫描 ====================
+// 目录统计参数 (一次扫描内共享)
+struct DirStatsOptions {
+    enable_smart_filter: bool,
+    exclude_tagged_caches: bool, // 把带缓存标记的目录当作智能过滤排除项
+    top_n: usize,
+    age_field: TimeField,
+    now: SystemTime,
+}
 
-// 大文件扫描 (仅扫描超过阈值的文件)
-#[tauri::command]
-async fn scan_large_files(
-    path: String,
-    threshold_mb: u64,
-    window: Window,
-) -> Result<ScanResult, String> {
-    use rayon::prelude::*;
+// 使用 walkdir 计算目录大小 (支持智能过滤、硬链接去重)
+// 同时记录目录内最大的文件、文件大小分布和年龄分布
+// ignore_rules 为 path 所在扫描根目录的规则
+fn calculate_dir_stats(
+    path: &Path,
+    options: &DirStatsOptions,
+    ignore_rules: &IgnoreStack,
+    seen_inodes: &InodeSet,
+) -> DirStats {
     use walkdir::WalkDir;
 
-    init_rayon_pool();
-
-    let path_obj = Path::new(&path);
-    let threshold_bytes = threshold_mb * 1024 * 1024;
-
-    let start_time = SystemTime::now();
-    let current = Arc::new(AtomicUsize::new(0));
+    let enable_smart_filter = options.enable_smart_filter;
+    let mut stats = DirStats::new(options);
+    let mut ignore_rules = ignore_rules.clone();
 
-    // 遍历所有文件
-    let all_files: Vec<_> = WalkDir::new(path_obj)
+    let entries = WalkDir::new(path)
         .follow_links(false)
+        .max_depth(10) // 限制递归深度，避免无限深入
         .into_iter()
+        .filter_entry(|e| {
+            // 带缓存标记的目录和 .cleandirignore 中 [skip] 的条目整棵跳过
+            let is_tagged_cache = options.exclude_tagged_caches
+                && e.depth() > 0
+                && e.file_type().is_dir()
+                && is_tagged_cache_dir(e.path());
+            !is_tagged_cache && ignore_rules.allows_stats(e)
+        })
         .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .collect();
+        .filter(|e| {
+            // 应用智能过滤
+            !should_skip_path(e.path(), enable_smart_filter)
+        })
+        .filter(|e| e.file_type().is_file());
 
-    let total = all_files.len();
+    for entry in entries {
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        stats.add_file(entry.path(), &metadata, options, seen_inodes);
+    }
 
-    // 并行处理,找出大文件
-    let items: Vec<DiskItem> = all_files
-        .par_iter()
-        .filter_map(|entry| {
-            let entry_path = entry.path();
-            let metadata = entry_path.metadata().ok()?;
-            let size = metadata.blocks() * 512;
+    stats
+}
 
-            // 过滤小于阈值的文件
-            if size < threshold_bytes {
-                return None;
-            }
+// ==================== 快速扫描 (两阶段优化版) ====================
 
-            let name = entry_path.file_name()?.to_string_lossy().to_string();
-            let file_type = get_file_type(&name);
+// 一个根目录的扫描结果: 直接子项，以及整棵树的汇总
+struct RootScan {
+    items: Vec<DiskItem>,
+    stats: DirStats,
+}
 
-            // 更新进度
-            let curr = current.fetch_add(1, Ordering::Relaxed) + 1;
-            if curr % 100 == 0 {
-                let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
-                let percent = ((curr as f64 / total as f64) * 100.0) as u8;
-                let _ = window.emit(
-                    "scan-progress",
-                    ScanProgress {
-                        percent,
-                        current: curr,
-                        total,
-                        current_item: name.clone(),
-                        elapsed_seconds: elapsed,
-                        estimated_remaining_seconds: 0,
-                    },
-                );
-            }
+// 第一阶段: 读取根目录下的一个直接子项
+// 文件直接计入 stats，目录大小先设为 0，留给第二阶段并行计算
+fn read_top_level_item(
+    entry: &fs::DirEntry,
+    options: &DirStatsOptions,
+    ignore_rules: &IgnoreStack,
+    stats: &mut DirStats,
+    seen_inodes: &InodeSet,
+) -> Option<DiskItem> {
+    let entry_path = entry.path();
+    let name = entry.file_name().to_string_lossy().to_string();
 
-            Some(DiskItem {
+    // 跳过隐藏文件
+    if name.starts_with('.') {
+        return None;
+    }
+
+    // 智能过滤
+    if should_skip_path(&entry_path, options.enable_smart_filter) {
+        return None;
+    }
+
+    // .cleandirignore 中 [skip] 的条目不显示，受保护的条目照常统计并加以标记
+    let is_dir_entry = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+    let ignore_action = ignore_rules.action(&entry_path, is_dir_entry);
+    if ignore_action == Some(IgnoreAction::Skip) {
+        return None;
+    }
+
+    // 获取元数据
+    let metadata = match entry_path.metadata() {
+        Ok(m) => m,
+        Err(e) => {
+            let error_type = ErrorType::from(&e);
+            let error_msg = match error_type {
+                ErrorType::PermissionDenied => "无权限访问",
+                ErrorType::NotFound => "文件不存在",
+                ErrorType::IOError => "磁盘 I/O 错误",
+            };
+            return Some(DiskItem {
                 name,
                 path: entry_path.to_string_lossy().to_string(),
-                size,
+                size: 0,
                 is_directory: false,
                 item_count: 0,
-                file_type,
-                error: None,
-            })
-        })
-        .collect();
+                file_type: None,
+                error: Some(error_msg.to_string()),
+                ..Default::default()
+            });
+        }
+    };
+
+    let is_directory = metadata.is_dir();
+    let is_cache = is_directory && is_tagged_cache_dir(&entry_path);
+    if is_cache && options.exclude_tagged_caches {
+        return None;
+    }
+    let file_type = if is_directory {
+        Some("directory".to_string())
+    } else {
+        get_file_type(&name)
+    };
+
+    // 文件直接获取大小，目录先设为 0
+    let size = if is_directory {
+        0
+    } else {
+        metadata.blocks() * 512
+    };
+    if !is_directory {
+        stats.add_file(&entry_path, &metadata, options, seen_inodes);
+    }
+
+    Some(DiskItem {
+        name,
+        path: entry_path.to_string_lossy().to_string(),
+        size,
+        is_directory,
+        item_count: 0,
+        file_type,
+        error: None,
+        is_cache,
+        is_protected: ignore_action == Some(IgnoreAction::Protect),
+        ..Default::default()
+    })
+}
+
+// 两阶段扫描的公共实现，单目录和多根目录扫描共用
+// 每个根目录先读取直接子项 (第一阶段)，再并行计算其下的子目录 (第二阶段)
+fn scan_roots_two_phase(
+    roots: &[PathBuf],
+    window: &Window,
+    options: &DirStatsOptions,
+) -> Result<Vec<RootScan>, String> {
+    use rayon::prelude::*;
+
+    // 读取目录内容
+    let mut root_entries = Vec::with_capacity(roots.len());
+    for root in roots {
+        match fs::read_dir(root) {
+            Ok(entries) => root_entries.push(entries.filter_map(|e| e.ok()).collect::<Vec<_>>()),
+            Err(e) if roots.len() == 1 => return Err(format!("读取目录失败: {}", e)),
+            Err(e) => return Err(format!("{}: 读取目录失败: {}", root.display(), e)),
+        }
+    }
+
+    let total = root_entries.iter().map(Vec::len).sum();
+    let start_time = SystemTime::now();
+
+    // 发送初始进度
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 5,
+            current: 0,
+            total,
+            current_item: "正在读取目录列表...".to_string(),
+            elapsed_seconds: 0,
+            estimated_remaining_seconds: 0,
+        },
+    );
+
+    // 发送 20% 进度
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 20,
+            current: 0,
+            total,
+            current_item: "正在计算目录大小...".to_string(),
+            elapsed_seconds: start_time.elapsed().unwrap_or_default().as_secs(),
+            estimated_remaining_seconds: 0,
+        },
+    );
+
+    // 根目录按顺序逐个统计 (每个根目录内部并行)，
+    // 跨根目录的硬链接因此总是计入排在前面的根目录
+    let seen_inodes: InodeSet = Arc::new(Mutex::new(HashSet::new()));
+    let processed = AtomicUsize::new(0);
+    let mut scans: Vec<RootScan> = Vec::with_capacity(roots.len());
+    for (root, entries) in roots.iter().zip(&root_entries) {
+        let ignore_rules = IgnoreStack::for_dir(root);
+
+        // 第一阶段: 快速收集基本信息
+        let mut stats = DirStats::new(options);
+        let mut items: Vec<DiskItem> = entries
+            .iter()
+            .filter_map(|entry| {
+                read_top_level_item(entry, options, &ignore_rules, &mut stats, &seen_inodes)
+            })
+            .collect();
+        let dirs_count = items.iter().filter(|item| item.is_directory).count();
+        processed.fetch_add(entries.len() - dirs_count, Ordering::Relaxed);
+
+        // 第二阶段: 并行计算子目录大小
+        let dir_stats: Vec<(usize, DirStats)> = items
+            .par_iter()
+            .enumerate()
+            .filter(|(_, item)| item.is_directory)
+            .map(|(item_index, item)| {
+                let stats = calculate_dir_stats(
+                    Path::new(&item.path),
+                    options,
+                    &ignore_rules,
+                    &seen_inodes,
+                );
+
+                // 更新进度
+                let curr = processed.fetch_add(1, Ordering::Relaxed) + 1;
+                let base_percent = 20;
+                let progress_percent =
+                    base_percent + ((curr as f64 / total.max(1) as f64) * 75.0) as u8;
+
+                let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
+                let speed = if elapsed > 0 {
+                    curr as f64 / elapsed as f64
+                } else {
+                    0.0
+                };
+                let remaining = if speed > 0.0 && total > curr {
+                    ((total - curr) as f64 / speed) as u64
+                } else {
+                    0
+                };
+
+                let _ = window.emit(
+                    "scan-progress",
+                    ScanProgress {
+                        percent: progress_percent.min(95),
+                        current: curr,
+                        total,
+                        current_item: item.name.clone(),
+                        elapsed_seconds: elapsed,
+                        estimated_remaining_seconds: remaining,
+                    },
+                );
+
+                (item_index, stats)
+            })
+            .collect();
+
+        // 更新目录大小，并汇总到所属的根目录
+        for (item_index, dir_stats) in dir_stats {
+            let item = &mut items[item_index];
+            item.size = dir_stats.size;
+            item.largest_files = Some(dir_stats.largest_files.to_vec());
+            item.size_histogram = Some(dir_stats.histogram.to_buckets());
+            item.age_buckets = Some(dir_stats.ages.to_buckets());
+            stats.merge(&dir_stats);
+        }
+        scans.push(RootScan { items, stats });
+    }
+
+    // 发送 100% 完成信号
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 100,
+            current: total,
+            total,
+            current_item: "完成".to_string(),
+            elapsed_seconds: start_time.elapsed().unwrap_or_default().as_secs(),
+            estimated_remaining_seconds: 0,
+        },
+    );
+
+    Ok(scans)
+}
+
+// 传入单个 path 时返回该目录的直接子项；
+// 通过 paths 传入多个根目录时，结果的顶层是合成的: 每个根目录对应一项
+#[tauri::command]
+async fn scan_directory_fast(
+    path: Option<String>,
+    paths: Option<Vec<String>>,
+    window: Window,
+    enable_smart_filter: Option<bool>,
+    top_n: Option<usize>,
+    age_field: Option<TimeField>,
+    exclude_tagged_caches: Option<bool>,
+) -> Result<ScanResult, String> {
+    // 初始化线程池
+    init_rayon_pool();
+
+    let enable_filter = enable_smart_filter.unwrap_or(true);
+    let stats_options = DirStatsOptions {
+        enable_smart_filter: enable_filter,
+        exclude_tagged_caches: enable_filter && exclude_tagged_caches.unwrap_or(false),
+        top_n: top_n.unwrap_or(DEFAULT_TOP_N),
+        age_field: age_field.unwrap_or_default(),
+        now: SystemTime::now(),
+    };
+
+    let mut paths = paths.unwrap_or_default();
+    paths.extend(path);
+    if paths.is_empty() {
+        return Err("请至少选择一个目录".to_string());
+    }
+    if paths.len() > 1 {
+        let roots = resolve_overlapping_roots(&paths)?;
+        let root_paths: Vec<PathBuf> = roots.iter().map(|(root, _)| root.clone()).collect();
+        let scans = scan_roots_two_phase(&root_paths, &window, &stats_options)?;
+        return Ok(merge_root_scans(roots, scans, &stats_options));
+    }
+
+    let root = PathBuf::from(&paths[0]);
+    let RootScan { mut items, stats } = scan_roots_two_phase(&[root], &window, &stats_options)?
+        .pop()
+        .unwrap_or_else(|| RootScan {
+            items: Vec::new(),
+            stats: DirStats::new(&stats_options),
+        });
+
+    // 按优先级和大小排序
+    items.sort_by(|a, b| {
+        let a_priority = get_dir_priority(Path::new(&a.path));
+        let b_priority = get_dir_priority(Path::new(&b.path));
+        if a_priority != b_priority {
+            b_priority.cmp(&a_priority)
+        } else {
+            b.size.cmp(&a.size)
+        }
+    });
+
+    Ok(ScanResult {
+        items,
+        largest_files: Some(stats.largest_files.to_vec()),
+        size_histogram: Some(stats.histogram.to_buckets()),
+        age_buckets: Some(stats.ages.to_buckets()),
+        ..Default::default()
+    })
+}
+
+// ==================== 多根目录扫描 ====================
+
+// 单个根目录的汇总
+#[derive(Debug, Serialize, Clone)]
+struct RootTotal {
+    path: String,
+    size: u64,
+    file_count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    merged_roots: Vec<String>, // 嵌套在该目录内、已合并统计的其他根目录
+}
+
+// 规范化根目录并去掉嵌套的根目录，避免同一路径被统计两次
+// 返回 (根目录, 被合并进来的嵌套根目录)
+fn resolve_overlapping_roots(paths: &[String]) -> Result<Vec<(PathBuf, Vec<String>)>, String> {
+    let mut canonical: Vec<(PathBuf, &String)> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let resolved =
+            fs::canonicalize(path).map_err(|e| format!("{}: 无法访问目录: {}", path, e))?;
+        if !resolved.is_dir() {
+            return Err(format!("{}: 不是目录", path));
+        }
+        canonical.push((resolved, path));
+    }
+
+    // 浅层目录优先，后面遇到的嵌套目录并入已保留的祖先
+    canonical.sort_by_key(|(resolved, _)| resolved.components().count());
+    let mut roots: Vec<(PathBuf, Vec<String>)> = Vec::new();
+    for (resolved, original) in canonical {
+        match roots
+            .iter_mut()
+            .find(|(root, _)| resolved.starts_with(root))
+        {
+            Some((_, merged)) => merged.push(original.clone()),
+            None => roots.push((resolved, Vec::new())),
+        }
+    }
+    Ok(roots)
+}
+
+// 合成顶层: 每个根目录一项，同时汇总整体的最大文件和分布
+fn merge_root_scans(
+    roots: Vec<(PathBuf, Vec<String>)>,
+    scans: Vec<RootScan>,
+    options: &DirStatsOptions,
+) -> ScanResult {
+    let mut tree = DirStats::new(options);
+    let mut items = Vec::with_capacity(roots.len());
+    let mut root_totals = Vec::with_capacity(roots.len());
+    for ((root, merged_roots), scan) in roots.into_iter().zip(scans) {
+        let stats = scan.stats;
+        let path = root.to_string_lossy().to_string();
+        let file_count = stats.histogram.file_count() as usize;
+        tree.merge(&stats);
+
+        items.push(DiskItem {
+            name: path.clone(),
+            path: path.clone(),
+            size: stats.size,
+            is_directory: true,
+            item_count: file_count,
+            file_type: Some("directory".to_string()),
+            largest_files: Some(stats.largest_files.to_vec()),
+            size_histogram: Some(stats.histogram.to_buckets()),
+            age_buckets: Some(stats.ages.to_buckets()),
+            is_cache: is_tagged_cache_dir(&root),
+            ..Default::default()
+        });
+        root_totals.push(RootTotal {
+            path,
+            size: stats.size,
+            file_count,
+            merged_roots,
+        });
+    }
+    items.sort_by_key(|item| Reverse(item.size));
+
+    let warnings = root_totals
+        .iter()
+        .flat_map(|total| {
+            total
+                .merged_roots
+                .iter()
+                .map(move |merged| format!("{} 位于 {} 内，已合并统计", merged, total.path))
+        })
+        .collect();
+
+    ScanResult {
+        items,
+        largest_files: Some(tree.largest_files.to_vec()),
+        size_histogram: Some(tree.histogram.to_buckets()),
+        age_buckets: Some(tree.ages.to_buckets()),
+        root_totals: Some(root_totals),
+        warnings,
+    }
+}
+
+// ==================== 文件类型识别 ====================
+
+// 内置文件类型表 (类别 -> 扩展名)，用户可在配置文件中覆盖
+const BUILTIN_FILE_TYPES: &[(&str, &[&str])] = &[
+    ("video", &["mp4", "mov", "avi", "mkv", "wmv", "flv", "webm"]),
+    ("audio", &["mp3", "wav", "flac", "aac", "ogg", "m4a"]),
+    ("image", &["jpg", "jpeg", "png", "gif", "bmp", "svg", "webp", "heic"]),
+    (
+        "document",
+        &["pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "txt", "rtf"],
+    ),
+    ("archive", &["zip", "rar", "7z", "tar", "gz", "bz2", "xz"]),
+    ("application", &["dmg", "pkg", "app", "exe"]),
+    ("cache", &["log", "tmp", "cache"]),
+    (
+        "code",
+        &[
+            "rs", "c", "h", "cc", "cpp", "hpp", "go", "java", "kt", "swift", "py", "rb", "php",
+            "js", "jsx", "ts", "tsx", "vue", "cs", "scala", "lua", "sh", "zsh", "sql", "html",
+            "css", "scss", "json", "yaml", "yml", "toml", "xml", "md",
+        ],
+    ),
+    (
+        "disk_image",
+        &["iso", "img", "qcow2", "vmdk", "vdi", "vhd", "vhdx", "raw"],
+    ),
+    (
+        "model",
+        &[
+            "safetensors", "gguf", "ggml", "ckpt", "onnx", "pt", "pth", "h5", "tflite", "mlmodel",
+        ],
+    ),
+    (
+        "database",
+        &["db", "sqlite", "sqlite3", "mdb", "accdb", "frm", "ibd", "ldb", "dbf"],
+    ),
+    ("font", &["ttf", "otf", "woff", "woff2", "ttc", "fon"]),
+    ("package", &["deb", "rpm", "appimage", "snap", "flatpak", "apk"]),
+];
+
+// 用户自定义文件类型配置: <配置目录>/CleanDir/file_types.json
+// 格式: { "类别": ["扩展名", ...] }，同一扩展名以用户配置为准
+const FILE_TYPES_CONFIG: &str = "file_types.json";
+
+lazy_static! {
+    // 扩展名 (小写) -> 类别
+    static ref FILE_TYPE_TABLE: RwLock<HashMap<String, String>> =
+        RwLock::new(build_file_type_table());
+}
+
+// 应用配置目录 (不存在时不会自动创建)
+fn app_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("CleanDir"))
+}
+
+fn load_file_type_overrides() -> HashMap<String, Vec<String>> {
+    let config_path = match app_config_dir() {
+        Some(dir) => dir.join(FILE_TYPES_CONFIG),
+        None => return HashMap::new(),
+    };
+
+    match fs::read_to_string(&config_path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            println!("警告：文件类型配置解析失败 {}: {}", config_path.display(), e);
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(), // 配置文件不存在时使用内置表
+    }
+}
+
+fn build_file_type_table() -> HashMap<String, String> {
+    let mut table = HashMap::new();
+
+    for (category, extensions) in BUILTIN_FILE_TYPES {
+        for ext in *extensions {
+            table.insert(ext.to_string(), category.to_string());
+        }
+    }
+
+    // 用户配置覆盖内置规则
+    for (category, extensions) in load_file_type_overrides() {
+        for ext in extensions {
+            let ext = ext.trim_start_matches('.').to_lowercase();
+            table.insert(ext, category.clone());
+        }
+    }
+
+    table
+}
+
+fn get_file_type(filename: &str) -> Option<String> {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let table = FILE_TYPE_TABLE.read().unwrap();
+    match table.get(&extension) {
+        Some(category) => Some(category.clone()),
+        None => Some("other".to_string()),
+    }
+}
+
+// 获取当前生效的文件类型表 (类别 -> 扩展名列表)
+#[tauri::command]
+fn get_file_types() -> HashMap<String, Vec<String>> {
+    let table = FILE_TYPE_TABLE.read().unwrap();
+    let mut categories: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (ext, category) in table.iter() {
+        categories
+            .entry(category.clone())
+            .or_default()
+            .push(ext.clone());
+    }
+    for extensions in categories.values_mut() {
+        extensions.sort();
+    }
+
+    categories
+}
+
+// 重新读取用户配置 (修改 file_types.json 后无需重启)
+#[tauri::command]
+fn reload_file_types() -> HashMap<String, Vec<String>> {
+    let table = build_file_type_table();
+    *FILE_TYPE_TABLE.write().unwrap() = table;
+    get_file_types()
+}
+
+// ==================== 完整扫描 (与快速扫描相同) ====================
+
+#[tauri::command]
+async fn scan_directory(
+    path: String,
+    window: Window,
+    enable_smart_filter: Option<bool>,
+    top_n: Option<usize>,
+    age_field: Option<TimeField>,
+    exclude_tagged_caches: Option<bool>,
+) -> Result<ScanResult, String> {
+    scan_directory_fast(
+        Some(path),
+        None,
+        window,
+        enable_smart_filter,
+        top_n,
+        age_field,
+        exclude_tagged_caches,
+    )
+    .await
+}
+
+// ==================== 安全删除 (优化版：移除预处理，直接删除) ====================
+
+#[tauri::command]
+async fn delete_items(window: Window, paths: Vec<String>) -> Result<String, String> {
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    let mut deleted = Vec::new();
+    let mut errors = Vec::new();
+    let total = paths.len();
+
+    for (index, path) in paths.iter().enumerate() {
+        let path_obj = Path::new(&path);
+
+        // 发送进度更新
+        let percent = ((index as f64 / total as f64) * 100.0) as u8;
+        let _ = window.emit("delete-progress", serde_json::json!({
+            "percent": percent,
+            "current": index + 1,
+            "total": total,
+            "current_item": Path::new(&path).file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("未知文件")
+                .to_string()
+        }));
+
+        if !path_obj.exists() {
+            errors.push(format!("{}: 文件不存在", path));
+            continue;
+        }
+
+        // 受 .cleandirignore 保护的路径 (或包含受保护条目的目录) 拒绝删除
+        if let Some(reason) = deletion_blocked(path_obj) {
+            errors.push(format!("{}: {}", path, reason));
+            continue;
+        }
+
+        // 🚀 关键优化：不做预先统计！(目录只为检查保护规则遍历一次子树)
+        // 移除耗时的 calculate_dir_size_walkdir 和 walkdir count 操作
+
+        let start_time = Instant::now();
+
+        // 执行删除命令
+        let result = Command::new("osascript")
+            .arg("-e")
+            .arg(format!(
+                "tell application \"Finder\" to delete POSIX file \"{}\"",
+                path
+            ))
+            .output();
+
+        match result {
+            Ok(output) => {
+                if output.status.success() {
+                    deleted.push(path.clone());
+                } else {
+                    let error_msg = String::from_utf8_lossy(&output.stderr);
+                    errors.push(format!("{}: {}", path, error_msg));
+                }
+            }
+            Err(e) => {
+                // 如果命令执行失败，可能是权限问题或其他系统错误
+                errors.push(format!("{}: 删除失败 - {}", path, e));
+            }
+        }
+
+        // 检查是否超时（虽然不太可能，但作为安全措施）
+        if start_time.elapsed() > Duration::from_secs(60) {
+            println!("警告：删除 {} 耗时过长 ({}s)", path, start_time.elapsed().as_secs());
+        }
+    }
+
+    // 发送完成信号
+    let _ = window.emit("delete-progress", serde_json::json!({
+        "percent": 100,
+        "current": total,
+        "total": total,
+        "current_item": "完成"
+    }));
+
+    // 返回删除结果摘要
+    if errors.is_empty() {
+        Ok(format!("成功删除 {} 项", deleted.len()))
+    } else {
+        Err(format!(
+            "成功: {}, 失败: {}\n错误详情:\n{}",
+            deleted.len(),
+            errors.len(),
+            errors.join("\n")
+        ))
+    }
+}
+
+// ==================== 权限检测 ====================
+
+#[tauri::command]
+fn check_disk_access_permission() -> Result<bool, String> {
+    // 尝试访问一个受保护的目录
+    let test_path = Path::new("/Library/Application Support/com.apple.TCC");
+
+    match fs::read_dir(test_path) {
+        Ok(_) => Ok(true),
+        Err(_) => Ok(false),
+    }
+}
+
+// ==================== 获取用户主目录 ====================
+
+#[tauri::command]
+fn get_home_dir() -> Result<String, String> {
+    match dirs::home_dir() {
+        Some(path) => Ok(path.to_string_lossy().to_string()),
+        None => Err("无法获取用户主目录".to_string()),
+    }
+}
+
+// ==================== 专项扫描 ====================
+
+// 大文件扫描 (仅扫描超过阈值的文件)
+// 指定 dir_threshold_mb 时，同时返回累计大小超过该阈值的目录 (大量中等文件堆积)
+#[tauri::command]
+async fn scan_large_files(
+    path: String,
+    threshold_mb: u64,
+    window: Window,
+    dir_threshold_mb: Option<u64>,
+) -> Result<ScanResult, String> {
+    use rayon::prelude::*;
+    use walkdir::WalkDir;
+
+    init_rayon_pool();
+
+    let path_obj = Path::new(&path);
+    let threshold_bytes = threshold_mb * 1024 * 1024;
+
+    let start_time = SystemTime::now();
+    let current = Arc::new(AtomicUsize::new(0));
+
+    // 遍历所有文件 (受保护的文件计入目录汇总，但不作为候选)
+    let mut ignore_rules = IgnoreStack::default();
+    let mut protected = HashSet::new();
+    let all_files: Vec<_> = WalkDir::new(path_obj)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| ignore_rules.allows_tracking(e, &mut protected))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .collect();
+
+    let total = all_files.len();
+
+    // 并行处理,找出大文件
+    let items: Vec<DiskItem> = all_files
+        .par_iter()
+        .filter_map(|entry| {
+            let entry_path = entry.path();
+            if protected.contains(entry_path) {
+                return None;
+            }
+            let metadata = entry_path.metadata().ok()?;
+            let size = metadata.blocks() * 512;
+
+            // 过滤小于阈值的文件
+            if size < threshold_bytes {
+                return None;
+            }
+
+            let name = entry_path.file_name()?.to_string_lossy().to_string();
+            let file_type = get_file_type(&name);
+
+            // 更新进度
+            let curr = current.fetch_add(1, Ordering::Relaxed) + 1;
+            if curr % 100 == 0 {
+                let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
+                let percent = ((curr as f64 / total as f64) * 100.0) as u8;
+                let _ = window.emit(
+                    "scan-progress",
+                    ScanProgress {
+                        percent,
+                        current: curr,
+                        total,
+                        current_item: name.clone(),
+                        elapsed_seconds: elapsed,
+                        estimated_remaining_seconds: 0,
+                    },
+                );
+            }
+
+            Some(DiskItem {
+                name,
+                path: entry_path.to_string_lossy().to_string(),
+                size,
+                is_directory: false,
+                item_count: 0,
+                file_type,
+                error: None,
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    let mut items = items;
+
+    // 大目录模式: 累计所有文件 (含小文件) 的大小
+    if let Some(dir_threshold_mb) = dir_threshold_mb {
+        let files: Vec<(PathBuf, u64)> = all_files
+            .par_iter()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path().to_path_buf(), metadata.blocks() * 512))
+            })
+            .collect();
+
+        let dirs = aggregate_to_ancestors(
+            path_obj,
+            files.iter().map(|(p, size)| (p.as_path(), *size, None)),
+        );
+        items.extend(
+            collect_large_directories(path_obj, &dirs, dir_threshold_mb * 1024 * 1024)
+                .into_iter()
+                .filter(|item| !protected.contains(Path::new(&item.path))),
+        );
+    }
+
+    // 按大小降序排序
+    items.sort_by(|a, b| b.size.cmp(&a.size));
+
+    Ok(ScanResult {
+        items,
+        ..Default::default()
+    })
+}
+
+// 旧文件扫描 (扫描超过指定天数未修改/访问的文件)
+#[tauri::command]
+async fn scan_old_files(
+    path: String,
+    days_threshold: u64,
+    window: Window,
+    time_field: Option<TimeField>,
+    mode: Option<OldFilesMode>,
+) -> Result<ScanResult, String> {
+    use rayon::prelude::*;
+    use walkdir::WalkDir;
+
+    init_rayon_pool();
+
+    let path_obj = Path::new(&path);
+    let now = SystemTime::now();
+    let threshold_duration = std::time::Duration::from_secs(days_threshold * 24 * 60 * 60);
+    let time_field = time_field.unwrap_or_default();
+
+    let mut warnings = Vec::new();
+    match time_field {
+        TimeField::Accessed => warnings.extend(atime_reliability_warning(path_obj)),
+        TimeField::Created => {
+            // 先用根目录探测文件系统是否支持创建时间
+            let metadata = path_obj
+                .metadata()
+                .map_err(|e| format!("读取目录失败: {}", e))?;
+            if file_time(&metadata, TimeField::Created).is_none() {
+                return Err("当前文件系统不支持创建时间".to_string());
+            }
+        }
+        _ => {}
+    }
+
+    let start_time = SystemTime::now();
+    let current = Arc::new(AtomicUsize::new(0));
+
+    // 遍历所有文件 (受保护的文件计入目录汇总，但不作为候选)
+    let mut ignore_rules = IgnoreStack::default();
+    let mut protected = HashSet::new();
+    let all_files: Vec<_> = WalkDir::new(path_obj)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| ignore_rules.allows_tracking(e, &mut protected))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .collect();
+
+    let total = all_files.len();
+
+    // 目录模式: 以整个目录为单位判断，便于整体归档废弃项目
+    if mode.unwrap_or_default() == OldFilesMode::Directories {
+        let files: Vec<(PathBuf, u64, Option<SystemTime>)> = all_files
+            .par_iter()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                // 读不到时间的文件按最新处理，避免误报
+                let time = file_time(&metadata, time_field).unwrap_or(now);
+                Some((entry.path().to_path_buf(), metadata.blocks() * 512, Some(time)))
+            })
+            .collect();
+
+        let dirs = aggregate_to_ancestors(
+            path_obj,
+            files.iter().map(|(p, size, time)| (p.as_path(), *size, *time)),
+        );
+        let mut items = collect_stale_directories(path_obj, &dirs, now, threshold_duration);
+        items.retain(|item| !protected.contains(Path::new(&item.path)));
+        items.sort_by_key(|item| Reverse(item.size));
+
+        return Ok(ScanResult {
+            items,
+            warnings,
+            ..Default::default()
+        });
+    }
+
+    // 并行处理,找出旧文件
+    let items: Vec<DiskItem> = all_files
+        .par_iter()
+        .filter_map(|entry| {
+            let entry_path = entry.path();
+            if protected.contains(entry_path) {
+                return None;
+            }
+            let metadata = entry_path.metadata().ok()?;
+
+            // 检查指定的时间戳
+            let time = file_time(&metadata, time_field)?;
+            let age = now.duration_since(time).ok()?;
+
+            // 过滤未超过时间阈值的文件
+            if age < threshold_duration {
+                return None;
+            }
+
+            let name = entry_path.file_name()?.to_string_lossy().to_string();
+            let size = metadata.blocks() * 512;
+            let file_type = get_file_type(&name);
+
+            // 更新进度
+            let curr = current.fetch_add(1, Ordering::Relaxed) + 1;
+            if curr % 100 == 0 {
+                let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
+                let percent = ((curr as f64 / total as f64) * 100.0) as u8;
+                let _ = window.emit(
+                    "scan-progress",
+                    ScanProgress {
+                        percent,
+                        current: curr,
+                        total,
+                        current_item: name.clone(),
+                        elapsed_seconds: elapsed,
+                        estimated_remaining_seconds: 0,
+                    },
+                );
+            }
+
+            Some(DiskItem {
+                name,
+                path: entry_path.to_string_lossy().to_string(),
+                size,
+                is_directory: false,
+                item_count: 0,
+                file_type,
+                error: None,
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    // 按大小降序排序
+    let mut items = items;
+    items.sort_by(|a, b| b.size.cmp(&a.size));
+
+    Ok(ScanResult {
+        items,
+        warnings,
+        ..Default::default()
+    })
+}
+
+// 重复文件扫描 (大小 -> 部分哈希 -> 全量 BLAKE3 哈希，逐级筛选)
+#[tauri::command]
+async fn scan_duplicate_files(
+    path: String,
+    window: Window,
+    use_cache: Option<bool>,
+    io_threads_per_device: Option<usize>,
+) -> Result<DuplicateScanResult, String> {
+    let roots = vec![DuplicateRoot {
+        path,
+        reference: false,
+    }];
+    run_duplicate_scan(
+        &roots,
+        Some(&window),
+        use_cache.unwrap_or(true),
+        io_threads_per_device.unwrap_or(DEFAULT_IO_THREADS_PER_DEVICE),
+    )
+}
+
+// 多目录重复文件扫描
+// 有参考目录时，只报告在参考目录中存在已验证副本的候选文件 (参考目录中的文件永远不会被建议删除)
+#[tauri::command]
+async fn scan_duplicate_files_multi(
+    roots: Vec<DuplicateRoot>,
+    window: Window,
+    use_cache: Option<bool>,
+    io_threads_per_device: Option<usize>,
+) -> Result<DuplicateScanResult, String> {
+    if roots.is_empty() {
+        return Err("请至少选择一个目录".to_string());
+    }
+    if roots.iter().all(|root| root.reference) {
+        return Err("请至少选择一个非参考目录".to_string());
+    }
+    run_duplicate_scan(
+        &roots,
+        Some(&window),
+        use_cache.unwrap_or(true),
+        io_threads_per_device.unwrap_or(DEFAULT_IO_THREADS_PER_DEVICE),
+    )
+}
+
+fn run_duplicate_scan(
+    roots: &[DuplicateRoot],
+    window: Option<&Window>,
+    use_cache: bool,
+    io_threads_per_device: usize,
+) -> Result<DuplicateScanResult, String> {
+    use walkdir::WalkDir;
+
+    init_rayon_pool();
+
+    let reference_roots: Vec<&Path> = roots
+        .iter()
+        .filter(|root| root.reference)
+        .map(|root| Path::new(&root.path))
+        .collect();
+    let is_reference = |path: &Path| reference_roots.iter().any(|root| path.starts_with(root));
+
+    // 收集所有普通文件 (目录重叠时同一路径只记一次)
+    let mut ignore_rules = IgnoreStack::default();
+    let mut seen_paths: HashSet<PathBuf> = HashSet::new();
+    let mut files: Vec<HashCandidate> = Vec::new();
+    for root in roots {
+        let root_path = Path::new(&root.path);
+        if !root_path.exists() {
+            return Err(format!("{}: 目录不存在", root.path));
+        }
+        for entry in WalkDir::new(root_path)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|e| ignore_rules.allows(e))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            if !seen_paths.insert(entry.path().to_path_buf()) {
+                continue;
+            }
+            if let Ok(metadata) = entry.metadata() {
+                files.push(HashCandidate::new(entry.into_path(), &metadata));
+            }
+        }
+    }
+
+    let cache = if use_cache { Some(&*HASH_CACHE) } else { None };
+    let duplicates = find_duplicate_groups(files, window, cache, io_threads_per_device);
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save() {
+            println!("警告：保存哈希缓存失败: {}", e);
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut owners = OwnerCache::default();
+
+    for (hash, members) in duplicates {
+        // 找到重复文件!
+        let size = members[0].size;
+        let files: Vec<DuplicateFile> = members
+            .iter()
+            .map(|member| {
+                let metadata = member.path.symlink_metadata().ok();
+                DuplicateFile {
+                    name: member
+                        .path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                    path: member.path.to_string_lossy().to_string(),
+                    modified: metadata
+                        .as_ref()
+                        .and_then(|m| m.modified().ok())
+                        .map(unix_seconds),
+                    owner: metadata.as_ref().map(|m| owners.get(m.uid())),
+                    hard_links: member
+                        .links
+                        .iter()
+                        .map(|link| link.to_string_lossy().to_string())
+                        .collect(),
+                    already_linked: !member.links.is_empty()
+                        || metadata.as_ref().map(|m| m.nlink() > 1).unwrap_or(false),
+                    // 任一硬链接位于参考目录，整个 inode 都视为参考副本
+                    is_reference: is_reference(&member.path)
+                        || member.links.iter().any(|link| is_reference(link)),
+                }
+            })
+            .collect();
+
+        let reference_count = files.iter().filter(|f| f.is_reference).count();
+        let reclaimable_bytes = if reference_roots.is_empty() {
+            size * (files.len() as u64 - 1)
+        } else if reference_count == 0 || reference_count == files.len() {
+            continue; // 没有参考副本，或全部位于参考目录，不需要报告
+        } else {
+            size * (files.len() - reference_count) as u64 // 参考目录已有副本，候选文件都可删除
+        };
+
+        groups.push(DuplicateGroup {
+            hash,
+            size,
+            file_type: get_file_type(&files[0].name),
+            reclaimable_bytes,
+            files,
+        });
+    }
+
+    // 按可释放空间降序排序
+    groups.sort_by_key(|g| Reverse(g.reclaimable_bytes));
+    let total_reclaimable_bytes = groups.iter().map(|g| g.reclaimable_bytes).sum();
+
+    Ok(DuplicateScanResult {
+        groups,
+        total_reclaimable_bytes,
+    })
+}
+
+// ==================== 重复文件保留策略 ====================
+
+// 每组保留哪一份
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum KeepStrategy {
+    Oldest,             // 修改时间最早的
+    Newest,             // 修改时间最新的
+    ShortestPath,       // 路径最短的
+    PreferredDirectory, // 位于 preferred_dir 下的 (组内没有时退回 Oldest)
+}
+
+#[derive(Debug, Deserialize)]
+struct KeeperPolicy {
+    strategy: KeepStrategy,
+    #[serde(default)]
+    preferred_dir: Option<String>,
+    #[serde(default)]
+    protected_dirs: Vec<String>, // 这些目录下的副本永远不会被标记删除
+}
+
+#[derive(Debug, Serialize)]
+struct GroupSelection {
+    hash: String,
+    keep: String,
+    remove: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    protected: Vec<String>, // 因位于受保护目录而额外保留的副本
+    freed_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct KeeperSelection {
+    selections: Vec<GroupSelection>,
+    total_freed_bytes: u64,
+}
+
+// 按策略从候选中选出保留的文件
+fn pick_keeper<'a>(
+    candidates: &[&'a DuplicateFile],
+    strategy: KeepStrategy,
+    preferred_dir: Option<&Path>,
+) -> Option<&'a DuplicateFile> {
+    // 时间未知的文件排在最后，同条件下按路径排序保证结果稳定
+    let oldest = || {
+        candidates
+            .iter()
+            .min_by_key(|f| (f.modified.unwrap_or(u64::MAX), f.path.as_str()))
+            .copied()
+    };
+
+    match strategy {
+        KeepStrategy::Oldest => oldest(),
+        KeepStrategy::Newest => candidates
+            .iter()
+            .max_by_key(|f| (f.modified.unwrap_or(0), Reverse(f.path.as_str())))
+            .copied(),
+        KeepStrategy::ShortestPath => candidates
+            .iter()
+            .min_by_key(|f| (f.path.len(), f.path.as_str()))
+            .copied(),
+        KeepStrategy::PreferredDirectory => {
+            let preferred: Vec<&DuplicateFile> = match preferred_dir {
+                Some(dir) => candidates
+                    .iter()
+                    .filter(|f| Path::new(&f.path).starts_with(dir))
+                    .copied()
+                    .collect(),
+                None => Vec::new(),
+            };
+            if preferred.is_empty() {
+                oldest()
+            } else {
+                pick_keeper(&preferred, KeepStrategy::Oldest, None)
+            }
+        }
+    }
+}
+
+// 为每个重复组选出保留的副本，其余标记删除
+#[tauri::command]
+fn select_duplicate_keepers(
+    groups: Vec<DuplicateGroup>,
+    policy: KeeperPolicy,
+) -> Result<KeeperSelection, String> {
+    if policy.strategy == KeepStrategy::PreferredDirectory && policy.preferred_dir.is_none() {
+        return Err("请指定优先保留的目录".to_string());
+    }
+
+    let preferred_dir = policy.preferred_dir.as_ref().map(Path::new);
+    let protected_dirs: Vec<&Path> = policy.protected_dirs.iter().map(Path::new).collect();
+    // 参考副本和受保护目录中的副本都不能删除 (任一硬链接路径受保护即整个 inode 受保护)
+    let is_protected = |file: &DuplicateFile| {
+        file.is_reference
+            || file.all_paths().any(|path| {
+                protected_dirs
+                    .iter()
+                    .any(|dir| Path::new(path).starts_with(dir))
+            })
+    };
+
+    let mut selections = Vec::new();
+    for group in &groups {
+        let all: Vec<&DuplicateFile> = group.files.iter().collect();
+        let protected: Vec<&DuplicateFile> =
+            group.files.iter().filter(|f| is_protected(f)).collect();
+
+        // 有受保护的副本时，保留的那份从受保护副本中选，其余全部可删
+        let pool = if protected.is_empty() {
+            &all
+        } else {
+            &protected
+        };
+        let keeper = match pick_keeper(pool, policy.strategy, preferred_dir) {
+            Some(keeper) => keeper,
+            None => continue,
+        };
+
+        // 删除一个 inode 需要删除它的全部硬链接
+        let removed: Vec<&DuplicateFile> = group
+            .files
+            .iter()
+            .filter(|f| f.path != keeper.path && !is_protected(f))
+            .collect();
+        let remove: Vec<String> = removed
+            .iter()
+            .flat_map(|f| f.all_paths().cloned())
+            .collect();
+        let also_kept: Vec<String> = protected
+            .iter()
+            .filter(|f| f.path != keeper.path)
+            .map(|f| f.path.clone())
+            .collect();
+
+        selections.push(GroupSelection {
+            hash: group.hash.clone(),
+            keep: keeper.path.clone(),
+            freed_bytes: group.size * removed.len() as u64,
+            remove,
+            protected: also_kept,
+        });
+    }
+
+    selections.sort_by_key(|s| Reverse(s.freed_bytes));
+    let total_freed_bytes = selections.iter().map(|s| s.freed_bytes).sum();
+
+    Ok(KeeperSelection {
+        selections,
+        total_freed_bytes,
+    })
+}
+
+// ==================== 重复文件去重 (硬链接 / reflink) ====================
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum DedupeMethod {
+    #[default]
+    Hardlink, // 所有路径指向同一个 inode
+    Reflink, // 写时复制克隆 (btrfs/XFS 的 FICLONE，APFS 的 clonefile)
+}
+
+#[derive(Debug, Serialize)]
+struct DedupeResult {
+    method: DedupeMethod,
+    replaced: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<String>, // 已经是同一 inode 的硬链接，无需处理
+    freed_bytes: u64,
+}
+
+// 创建 src 的写时复制克隆 dst (dst 不能已存在)
+#[cfg(target_os = "linux")]
+fn reflink_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let src_file = fs::File::open(src)?;
+    let dst_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+
+    let ret = unsafe { libc::ioctl(dst_file.as_raw_fd(), libc::FICLONE, src_file.as_raw_fd()) };
+    if ret != 0 {
+        let error = std::io::Error::last_os_error();
+        drop(dst_file);
+        let _ = fs::remove_file(dst);
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn reflink_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(src.as_os_str().as_bytes())?;
+    let dst = CString::new(dst.as_os_str().as_bytes())?;
+    let ret = unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink_file(_src: &Path, _dst: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "当前系统不支持 reflink",
+    ))
+}
+
+// 在同一目录下生成尚不存在的隐藏临时文件名 (带随机后缀，避免覆盖已有文件)
+fn sibling_temp_path(path: &Path, suffix: &str) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let unique = format!(
+            "{:x}{:x}{:x}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos
+        );
+        let candidate = path.with_file_name(format!(".{}.cleandir-{}-{}", name, suffix, unique));
+        if candidate.symlink_metadata().is_err() {
+            return candidate;
+        }
+    }
+}
+
+// 为 target 生成指向 keep 内容的新文件，并恢复 target 原有的权限和时间戳
+fn create_linked_copy(
+    keep: &Path,
+    target_metadata: &fs::Metadata,
+    tmp: &Path,
+    method: DedupeMethod,
+) -> std::io::Result<()> {
+    match method {
+        // 硬链接共享 inode，权限、属主和修改时间与保留文件一致 (调用前已确认相同)
+        DedupeMethod::Hardlink => fs::hard_link(keep, tmp),
+        DedupeMethod::Reflink => {
+            reflink_file(keep, tmp)?;
+            let result = (|| {
+                // 先改时间再改权限: 只读的目标权限会让之后无法再以写方式打开
+                // (futimens 用只读句柄即可)
+                let times = fs::FileTimes::new()
+                    .set_accessed(target_metadata.accessed()?)
+                    .set_modified(target_metadata.modified()?);
+                fs::File::open(tmp)?.set_times(times)?;
+                // 属主需要权限才能修改，失败时保持当前用户
+                let _ = std::os::unix::fs::lchown(
+                    tmp,
+                    Some(target_metadata.uid()),
+                    Some(target_metadata.gid()),
+                );
+                fs::set_permissions(tmp, target_metadata.permissions())
+            })();
+            if result.is_err() {
+                let _ = fs::remove_file(tmp);
+            }
+            result
+        }
+    }
+}
+
+// 替换前再次确认 target 与保留文件内容一致，且可以安全替换
+fn verify_dedupe_target(
+    keep_metadata: &fs::Metadata,
+    keep_hash: &str,
+    target: &Path,
+    method: DedupeMethod,
+) -> Result<fs::Metadata, String> {
+    let metadata = target
+        .symlink_metadata()
+        .map_err(|e| format!("{}: {}", target.display(), e))?;
+
+    if !metadata.is_file() {
+        return Err(format!("{}: 不是普通文件", target.display()));
+    }
+    if metadata.dev() != keep_metadata.dev() {
+        return Err(format!("{}: 与保留文件不在同一文件系统", target.display()));
+    }
+    if metadata.len() != keep_metadata.len() {
+        return Err(format!("{}: 文件大小已变化", target.display()));
+    }
+    if method == DedupeMethod::Hardlink
+        && (metadata.mode() != keep_metadata.mode()
+            || metadata.uid() != keep_metadata.uid()
+            || metadata.gid() != keep_metadata.gid())
+    {
+        return Err(format!(
+            "{}: 权限或属主与保留文件不同，硬链接会改变其权限，请改用 reflink",
+            target.display()
+        ));
+    }
+    if method == DedupeMethod::Hardlink
+        && (metadata.mtime() != keep_metadata.mtime()
+            || metadata.mtime_nsec() != keep_metadata.mtime_nsec())
+    {
+        return Err(format!(
+            "{}: 修改时间与保留文件不同，硬链接会改变其时间戳，请改用 reflink",
+            target.display()
+        ));
+    }
+
+    let hash = calculate_full_hash(target, &|_| {})
+        .map_err(|e| format!("{}: 读取失败 - {}", target.display(), e))?;
+    if hash != keep_hash {
+        return Err(format!("{}: 内容与保留文件不一致", target.display()));
+    }
+
+    Ok(metadata)
+}
+
+// 用硬链接或 reflink 替换重复副本，任何一步失败都会回滚已替换的文件
+#[tauri::command]
+async fn dedupe_group(
+    keep: String,
+    replace: Vec<String>,
+    method: Option<DedupeMethod>,
+) -> Result<DedupeResult, String> {
+    let method = method.unwrap_or_default();
+    let keep_path = Path::new(&keep);
+
+    let keep_metadata = keep_path
+        .symlink_metadata()
+        .map_err(|e| format!("{}: {}", keep, e))?;
+    if !keep_metadata.is_file() {
+        return Err(format!("{}: 不是普通文件", keep));
+    }
+    let keep_hash = calculate_full_hash(keep_path, &|_| {})
+        .map_err(|e| format!("{}: 读取失败 - {}", keep, e))?;
+
+    // 已替换的 (原路径, 备份路径)，用于回滚
+    let mut journal: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut skipped = Vec::new();
+
+    let rollback = |journal: &[(PathBuf, PathBuf)]| {
+        for (target, backup) in journal.iter().rev() {
+            if let Err(e) = fs::rename(backup, target) {
+                println!("警告：回滚 {} 失败: {}", target.display(), e);
+            }
+        }
+    };
+
+    for target in &replace {
+        let target_path = Path::new(target);
+
+        if let Ok(metadata) = target_path.symlink_metadata() {
+            if metadata.dev() == keep_metadata.dev() && metadata.ino() == keep_metadata.ino() {
+                skipped.push(target.clone());
+                continue;
+            }
+        }
+
+        let step = (|| -> Result<PathBuf, String> {
+            let target_metadata =
+                verify_dedupe_target(&keep_metadata, &keep_hash, target_path, method)?;
+
+            let tmp = sibling_temp_path(target_path, "tmp");
+            let backup = sibling_temp_path(target_path, "bak");
+            create_linked_copy(keep_path, &target_metadata, &tmp, method)
+                .map_err(|e| format!("{}: 创建链接失败 - {}", target, e))?;
+
+            // 先把原文件挪到备份位置，再把新文件放回原路径
+            // rename 会覆盖已存在的文件，备份路径被占用时放弃
+            if backup.symlink_metadata().is_ok() {
+                let _ = fs::remove_file(&tmp);
+                return Err(format!("{}: 备份路径已存在", target));
+            }
+            if let Err(e) = fs::rename(target_path, &backup) {
+                let _ = fs::remove_file(&tmp);
+                return Err(format!("{}: 备份失败 - {}", target, e));
+            }
+            if let Err(e) = fs::rename(&tmp, target_path) {
+                let _ = fs::rename(&backup, target_path);
+                let _ = fs::remove_file(&tmp);
+                return Err(format!("{}: 替换失败 - {}", target, e));
+            }
+            Ok(backup)
+        })();
+
+        match step {
+            Ok(backup) => journal.push((target_path.to_path_buf(), backup)),
+            Err(e) => {
+                rollback(&journal);
+                return Err(format!("去重失败，已回滚全部修改\n{}", e));
+            }
+        }
+    }
+
+    // 全部替换成功后再删除备份
+    for (target, backup) in &journal {
+        if let Err(e) = fs::remove_file(backup) {
+            println!("警告：删除备份 {} 失败: {}", target.display(), e);
+        }
+    }
+
+    let replaced: Vec<String> = journal
+        .iter()
+        .map(|(target, _)| target.to_string_lossy().to_string())
+        .collect();
+
+    Ok(DedupeResult {
+        method,
+        freed_bytes: keep_metadata.len() * replaced.len() as u64,
+        replaced,
+        skipped,
+    })
+}
+
+// ==================== 重复目录检测 (Merkle 哈希) ====================
+
+// 一组内容完全相同的目录树
+#[derive(Debug, Serialize, Clone)]
+struct DuplicateDirGroup {
+    hash: String,      // 目录树的 Merkle 哈希
+    size: u64,         // 单个目录的文件总大小
+    file_count: usize, // 单个目录内的文件数
+    directories: Vec<String>,
+    reclaimable_bytes: u64, // 只保留一份时可释放的空间: size × (n - 1)
+}
+
+#[derive(Debug, Serialize, Default)]
+struct DuplicateDirScanResult {
+    groups: Vec<DuplicateDirGroup>,
+    total_reclaimable_bytes: u64,
+}
+
+// 目录的直接子项
+enum TreeEntry {
+    File {
+        name: String,
+        candidate: HashCandidate,
+    },
+    Dir {
+        name: String,
+        path: PathBuf,
+    },
+    Symlink {
+        name: String,
+        target: String,
+    },
+}
+
+// 目录的汇总信息
+struct TreeNode {
+    signature: String, // 先是结构签名 (名称 + 大小)，确认候选后换成内容签名
+    size: u64,
+    file_count: usize,
+}
+
+// 按名称排序后对子项逐个编码，得到与遍历顺序无关的目录签名
+// content_hashes 为 None 时只比较结构 (文件名 + 大小)
+// 目录内有读取失败的内容时无法签名，返回 None
+fn tree_signature(
+    dir: &Path,
+    entries: &[TreeEntry],
+    nodes: &HashMap<PathBuf, TreeNode>,
+    unreadable: &HashSet<PathBuf>,
+    content_hashes: Option<&HashMap<PathBuf, String>>,
+) -> Option<String> {
+    if unreadable.contains(dir) {
+        return None;
+    }
+
+    let mut parts: Vec<String> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let part = match entry {
+            TreeEntry::File { name, candidate } => match content_hashes {
+                Some(hashes) => format!("f\0{}\0{}", name, hashes.get(&candidate.path)?),
+                None => format!("f\0{}\0{}", name, candidate.size),
+            },
+            TreeEntry::Dir { name, path } => {
+                format!("d\0{}\0{}", name, nodes.get(path)?.signature)
+            }
+            TreeEntry::Symlink { name, target } => format!("l\0{}\0{}", name, target),
+        };
+        parts.push(part);
+    }
+    parts.sort();
+
+    let mut hasher = blake3::Hasher::new();
+    for part in &parts {
+        hasher.update(part.as_bytes());
+        hasher.update(b"\n");
+    }
+    Some(hasher.finalize().to_hex().to_string())
+}
+
+// 自底向上计算每个目录的签名 (dirs 需按深度从深到浅排列)
+// 目录本身或子目录缺少签名 (遍历出错、文件读取失败) 时，该目录及其祖先不参与比较
+fn compute_tree_nodes(
+    dirs: &[PathBuf],
+    children: &HashMap<PathBuf, Vec<TreeEntry>>,
+    unreadable: &HashSet<PathBuf>,
+    content_hashes: Option<&HashMap<PathBuf, String>>,
+) -> HashMap<PathBuf, TreeNode> {
+    let mut nodes: HashMap<PathBuf, TreeNode> = HashMap::new();
+
+    for dir in dirs {
+        let entries = match children.get(dir) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        let signature = match tree_signature(dir, entries, &nodes, unreadable, content_hashes) {
+            Some(signature) => signature,
+            None => continue,
+        };
+
+        let mut size = 0;
+        let mut file_count = 0;
+        for entry in entries {
+            match entry {
+                TreeEntry::File { candidate, .. } => {
+                    size += candidate.size;
+                    file_count += 1;
+                }
+                TreeEntry::Dir { path, .. } => {
+                    if let Some(child) = nodes.get(path) {
+                        size += child.size;
+                        file_count += child.file_count;
+                    }
+                }
+                TreeEntry::Symlink { .. } => {}
+            }
+        }
+
+        nodes.insert(
+            dir.clone(),
+            TreeNode {
+                signature,
+                size,
+                file_count,
+            },
+        );
+    }
+
+    nodes
+}
+
+// 按签名分组，只保留非空且出现多次的目录
+fn group_by_signature(
+    nodes: &HashMap<PathBuf, TreeNode>,
+    root: &Path,
+) -> HashMap<String, Vec<PathBuf>> {
+    let mut groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for (dir, node) in nodes {
+        if dir != root && node.file_count > 0 {
+            groups
+                .entry(node.signature.clone())
+                .or_default()
+                .push(dir.clone());
+        }
+    }
+    groups.retain(|_, dirs| dirs.len() > 1);
+    groups
+}
+
+// 重复目录扫描: 先按结构 (文件名 + 大小) 筛出候选，再对候选内的文件做全量哈希确认
+#[tauri::command]
+async fn scan_duplicate_directories(
+    path: String,
+    window: Window,
+    use_cache: Option<bool>,
+    io_threads_per_device: Option<usize>,
+) -> Result<DuplicateDirScanResult, String> {
+    use walkdir::WalkDir;
+
+    init_rayon_pool();
+
+    let root = Path::new(&path);
+    if !root.is_dir() {
+        return Err(format!("{}: 不是目录", path));
+    }
+
+    // 第一步: 收集每个目录的直接子项
+    let mut children: HashMap<PathBuf, Vec<TreeEntry>> = HashMap::new();
+    children.insert(root.to_path_buf(), Vec::new());
+
+    // 有内容读取失败 (或有条目被 .cleandirignore 排除) 的目录及其祖先: 无法确认内容，不能当作重复
+    let mut unreadable: HashSet<PathBuf> = HashSet::new();
+    let mut mark_unreadable = |path: &Path| {
+        for dir in path.ancestors() {
+            if !dir.starts_with(root) || !unreadable.insert(dir.to_path_buf()) {
+                break; // 超出扫描根目录，或上层已标记过
+            }
+        }
+    };
+
+    let mut ignore_rules = IgnoreStack::default();
+    let mut excluded: Vec<PathBuf> = Vec::new();
+    for entry in WalkDir::new(root)
+        .follow_links(false)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| {
+            let allowed = ignore_rules.allows(e);
+            if !allowed {
+                excluded.push(e.path().to_path_buf());
+            }
+            allowed
+        })
+    {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                // 无法读取的目录 (或其父目录) 不参与比较
+                if let Some(path) = e.path() {
+                    mark_unreadable(path);
+                }
+                continue;
+            }
+        };
+        let entry_path = entry.path().to_path_buf();
+        let parent = match entry_path.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => continue,
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = entry.file_type();
+
+        let tree_entry = if file_type.is_dir() {
+            children.entry(entry_path.clone()).or_default();
+            TreeEntry::Dir {
+                name,
+                path: entry_path,
+            }
+        } else if file_type.is_symlink() {
+            let target = match fs::read_link(&entry_path) {
+                Ok(target) => target.to_string_lossy().to_string(),
+                Err(_) => {
+                    mark_unreadable(&parent);
+                    continue;
+                }
+            };
+            TreeEntry::Symlink { name, target }
+        } else if file_type.is_file() {
+            let metadata = match entry.metadata() {
+                Ok(m) => m,
+                Err(_) => {
+                    mark_unreadable(&parent);
+                    continue;
+                }
+            };
+            TreeEntry::File {
+                name,
+                candidate: HashCandidate::new(entry_path, &metadata),
+            }
+        } else {
+            continue; // 设备文件、socket 等不参与比较
+        };
+
+        children.entry(parent).or_default().push(tree_entry);
+    }
+    for path in &excluded {
+        if let Some(parent) = path.parent() {
+            mark_unreadable(parent);
+        }
+    }
+
+    // 从深到浅排列，保证子目录先于父目录计算
+    let mut dirs: Vec<PathBuf> = children.keys().cloned().collect();
+    dirs.sort_by_key(|dir| Reverse(dir.components().count()));
+
+    // 第二步: 结构签名相同的目录才是候选
+    let shape_nodes = compute_tree_nodes(&dirs, &children, &unreadable, None);
+    let shape_groups = group_by_signature(&shape_nodes, root);
+
+    // 只需哈希候选目录 (含嵌套候选) 内的文件
+    let candidate_dirs: HashSet<&Path> = shape_groups
+        .values()
+        .flatten()
+        .map(|dir| dir.as_path())
+        .collect();
+    let in_candidate = |dir: &Path| dir.ancestors().any(|a| candidate_dirs.contains(a));
+
+    let files: Vec<HashCandidate> = children
+        .iter()
+        .filter(|(dir, _)| in_candidate(dir))
+        .flat_map(|(_, entries)| entries.iter())
+        .filter_map(|entry| match entry {
+            TreeEntry::File { candidate, .. } => Some(candidate.clone()),
+            _ => None,
+        })
+        .collect();
+
+    // 第三步: 并行计算候选文件的全量哈希
+    let progress = HashProgressTracker::new(Some(&window), &files);
+    let cache = if use_cache.unwrap_or(true) {
+        Some(&*HASH_CACHE)
+    } else {
+        None
+    };
+    let content_hashes: HashMap<PathBuf, String> =
+        hash_files_parallel(
+            files,
+            io_threads_per_device.unwrap_or(DEFAULT_IO_THREADS_PER_DEVICE),
+            &progress,
+            cache,
+        )
+            .into_iter()
+            .map(|(candidate, hash)| (candidate.path, hash))
+            .collect();
+    progress.emit("完成".to_string());
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save() {
+            println!("警告：保存哈希缓存失败: {}", e);
+        }
+    }
+
+    // 第四步: 用内容签名重新比较候选目录
+    let candidate_children: HashMap<PathBuf, Vec<TreeEntry>> = children
+        .into_iter()
+        .filter(|(dir, _)| in_candidate(dir))
+        .collect();
+    let candidate_order: Vec<PathBuf> = dirs
+        .into_iter()
+        .filter(|dir| candidate_children.contains_key(dir))
+        .collect();
+    let content_nodes = compute_tree_nodes(
+        &candidate_order,
+        &candidate_children,
+        &unreadable,
+        Some(&content_hashes),
+    );
+    let content_groups = group_by_signature(&content_nodes, root);
+
+    // 只报告最上层的重复目录: 组内所有目录的父目录都已重复时，由父目录代表
+    let duplicated: HashSet<&Path> = content_groups
+        .values()
+        .flatten()
+        .map(|dir| dir.as_path())
+        .collect();
+    let mut groups: Vec<DuplicateDirGroup> = content_groups
+        .iter()
+        .filter(|(_, members)| {
+            members.iter().any(|dir| match dir.parent() {
+                Some(parent) => !duplicated.contains(parent),
+                None => true,
+            })
+        })
+        .map(|(hash, members)| {
+            let node = &content_nodes[&members[0]];
+            let mut directories: Vec<String> = members
+                .iter()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .collect();
+            directories.sort();
+            DuplicateDirGroup {
+                hash: hash.clone(),
+                size: node.size,
+                file_count: node.file_count,
+                reclaimable_bytes: node.size * (members.len() as u64 - 1),
+                directories,
+            }
+        })
+        .collect();
+
+    groups.sort_by_key(|g| Reverse(g.reclaimable_bytes));
+    let total_reclaimable_bytes = groups.iter().map(|g| g.reclaimable_bytes).sum();
+
+    Ok(DuplicateDirScanResult {
+        groups,
+        total_reclaimable_bytes,
+    })
+}
+
+// ==================== 相似图片检测 (感知哈希) ====================
+
+// 支持解码的图片扩展名 (HEIC 仅在 macOS 上通过 sips 转换)
+const SIMILAR_IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tif", "tiff", "heic", "heif",
+];
+
+const DEFAULT_IMAGE_HASH_DISTANCE: u32 = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum PerceptualHash {
+    #[default]
+    DHash, // 差值哈希: 速度快，对缩放和重新编码稳定
+    PHash, // DCT 哈希: 更慢，对亮度/对比度调整更稳定
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct SimilarImage {
+    name: String,
+    path: String,
+    size: u64,
+    width: u32,
+    height: u32,
+    hash: String,  // 64 位感知哈希 (十六进制)
+    distance: u32, // 与簇内第一张 (建议保留的) 的汉明距离
+}
+
+// 一组视觉上相似的图片，按分辨率、文件大小降序排列 (第一张为建议保留的)
+// 其余每张与第一张的距离都不超过阈值
+#[derive(Debug, Serialize, Clone)]
+struct ImageCluster {
+    images: Vec<SimilarImage>,
+    total_size: u64,
+    reclaimable_bytes: u64, // 只保留第一张时可释放的空间
+}
+
+#[derive(Debug, Serialize, Default)]
+struct SimilarImageScanResult {
+    clusters: Vec<ImageCluster>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+fn is_heic(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| matches!(e.to_lowercase().as_str(), "heic" | "heif"))
+        .unwrap_or(false)
+}
+
+// HEIC 需要借助系统的 sips 转成 JPEG 后再解码
+#[cfg(target_os = "macos")]
+fn decode_heic(path: &Path) -> Option<image::DynamicImage> {
+    use std::process::Command;
+
+    let tmp = std::env::temp_dir().join(format!(
+        "cleandir-heic-{}-{}.jpg",
+        std::process::id(),
+        blake3::hash(path.to_string_lossy().as_bytes()).to_hex()
+    ));
+    let status = Command::new("sips")
+        .args(["-s", "format", "jpeg"])
+        .arg(path)
+        .arg("--out")
+        .arg(&tmp)
+        .output()
+        .ok()?
+        .status;
+    let image = if status.success() {
+        image::open(&tmp).ok()
+    } else {
+        None
+    };
+    let _ = fs::remove_file(&tmp);
+    image
+}
+
+#[cfg(not(target_os = "macos"))]
+fn decode_heic(_path: &Path) -> Option<image::DynamicImage> {
+    None
+}
+
+fn decode_image(path: &Path) -> Option<image::DynamicImage> {
+    if is_heic(path) {
+        decode_heic(path)
+    } else {
+        image::open(path).ok()
+    }
+}
+
+// dHash: 缩成 9x8 灰度图，比较每行相邻像素的明暗
+fn dhash(image: &image::DynamicImage) -> u64 {
+    use image::imageops::FilterType;
+
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+// pHash: 缩成 32x32 灰度图做二维 DCT，取左上 8x8 低频系数与中位数比较
+fn phash(image: &image::DynamicImage) -> u64 {
+    use image::imageops::FilterType;
+    use std::f64::consts::PI;
+
+    const N: usize = 32;
+    let small = image
+        .resize_exact(N as u32, N as u32, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f64> = small.pixels().map(|p| p[0] as f64).collect();
+
+    // 可分离的 DCT-II: 先对行再对列
+    let cos_table: Vec<f64> = (0..N * N)
+        .map(|i| {
+            let (k, n) = (i / N, i % N);
+            ((2 * n + 1) as f64 * k as f64 * PI / (2 * N) as f64).cos()
+        })
+        .collect();
+    let dct_1d = |input: &[f64], output: &mut [f64], stride: usize| {
+        for k in 0..N {
+            output[k * stride] = (0..N)
+                .map(|n| input[n * stride] * cos_table[k * N + n])
+                .sum();
+        }
+    };
+
+    let mut rows = vec![0.0; N * N];
+    for y in 0..N {
+        dct_1d(&pixels[y * N..], &mut rows[y * N..], 1);
+    }
+    let mut coefficients = vec![0.0; N * N];
+    for x in 0..N {
+        dct_1d(&rows[x..], &mut coefficients[x..], N);
+    }
+
+    // 跳过直流分量 (整体亮度)
+    let low: Vec<f64> = (0..8)
+        .flat_map(|y| (0..8).map(move |x| (y, x)))
+        .filter(|&(y, x)| y != 0 || x != 0)
+        .map(|(y, x)| coefficients[y * N + x])
+        .collect();
+    let mut sorted = low.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let median = sorted[sorted.len() / 2];
+
+    low.iter()
+        .fold(0u64, |hash, &value| (hash << 1) | u64::from(value > median))
+}
+
+// 并查集，用于把两两相似的图片合并成簇
+struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> Self {
+        DisjointSet {
+            parent: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[rb] = ra;
+        }
+    }
+}
+
+// 并查集合并出的连通分量可能是 A~B~C 这样的链，A 与 C 并不相似
+// 这里以分辨率最高的图片为代表，只把距代表不超过阈值的图片归入一簇，剩下的继续分簇
+fn split_by_keeper(mut images: Vec<(SimilarImage, u64)>, max_distance: u32) -> Vec<ImageCluster> {
+    images.sort_by_key(|(image, _)| {
+        Reverse((u64::from(image.width) * u64::from(image.height), image.size))
+    });
+
+    let mut clusters = Vec::new();
+    let mut remaining = images.into_iter();
+    while let Some((keeper, keeper_hash)) = remaining.next() {
+        let mut cluster = vec![keeper];
+        let mut rest = Vec::new();
+        for (mut image, hash) in remaining {
+            let distance = (hash ^ keeper_hash).count_ones();
+            if distance <= max_distance {
+                image.distance = distance;
+                cluster.push(image);
+            } else {
+                rest.push((image, hash));
+            }
+        }
+        remaining = rest.into_iter();
+
+        if cluster.len() > 1 {
+            let total_size: u64 = cluster.iter().map(|image| image.size).sum();
+            clusters.push(ImageCluster {
+                reclaimable_bytes: total_size - cluster[0].size,
+                total_size,
+                images: cluster,
+            });
+        }
+    }
+    clusters
+}
+
+// 相似图片扫描: 计算感知哈希，把汉明距离不超过 max_distance 的图片聚成一簇
+#[tauri::command]
+async fn scan_similar_images(
+    path: String,
+    window: Window,
+    max_distance: Option<u32>,
+    algorithm: Option<PerceptualHash>,
+) -> Result<SimilarImageScanResult, String> {
+    use rayon::prelude::*;
+    use walkdir::WalkDir;
+
+    init_rayon_pool();
+
+    let max_distance = max_distance.unwrap_or(DEFAULT_IMAGE_HASH_DISTANCE);
+    let algorithm = algorithm.unwrap_or_default();
+    let start_time = SystemTime::now();
+
+    // 遍历所有图片文件
+    let mut ignore_rules = IgnoreStack::default();
+    let all_images: Vec<PathBuf> = WalkDir::new(Path::new(&path))
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| ignore_rules.allows(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| SIMILAR_IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                .unwrap_or(false)
+        })
+        .map(|e| e.into_path())
+        .collect();
+
+    // 只有 macOS 能借助 sips 解码 HEIC，其他系统直接跳过并明确提示
+    let mut warnings = Vec::new();
+    let all_images: Vec<PathBuf> = if cfg!(target_os = "macos") {
+        all_images
+    } else {
+        let (heic, others): (Vec<PathBuf>, Vec<PathBuf>) =
+            all_images.into_iter().partition(|p| is_heic(p));
+        if !heic.is_empty() {
+            warnings.push(format!(
+                "当前系统不支持解码 HEIC/HEIF，已跳过 {} 张图片 (无法与 JPEG 副本比较)",
+                heic.len()
+            ));
+        }
+        others
+    };
+
+    let total = all_images.len();
+    let current = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicUsize::new(0));
+
+    // 并行解码并计算感知哈希
+    let images: Vec<(SimilarImage, u64)> = all_images
+        .par_iter()
+        .filter_map(|image_path| {
+            let curr = current.fetch_add(1, Ordering::Relaxed) + 1;
+            if curr.is_multiple_of(20) {
+                let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
+                let speed = if elapsed > 0 {
+                    curr as f64 / elapsed as f64
+                } else {
+                    0.0
+                };
+                let remaining = if speed > 0.0 {
+                    ((total - curr) as f64 / speed) as u64
+                } else {
+                    0
+                };
+                let _ = window.emit(
+                    "scan-progress",
+                    ScanProgress {
+                        percent: ((curr as f64 / total as f64) * 100.0) as u8,
+                        current: curr,
+                        total,
+                        current_item: image_path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                        elapsed_seconds: elapsed,
+                        estimated_remaining_seconds: remaining,
+                    },
+                );
+            }
+
+            let decoded = match decode_image(image_path) {
+                Some(image) => image,
+                None => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+            };
+            let hash = match algorithm {
+                PerceptualHash::DHash => dhash(&decoded),
+                PerceptualHash::PHash => phash(&decoded),
+            };
+            let metadata = image_path.metadata().ok()?;
+
+            Some((
+                SimilarImage {
+                    name: image_path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                    path: image_path.to_string_lossy().to_string(),
+                    size: metadata.len(),
+                    width: decoded.width(),
+                    height: decoded.height(),
+                    hash: format!("{:016x}", hash),
+                    distance: 0,
+                },
+                hash,
+            ))
+        })
+        .collect();
+
+    // 两两比较汉明距离 (并行找出相似对，再用并查集合并)
+    let pairs: Vec<(usize, usize)> = (0..images.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let images = &images;
+            (i + 1..images.len())
+                .filter(move |&j| (images[i].1 ^ images[j].1).count_ones() <= max_distance)
+                .map(move |j| (i, j))
+        })
+        .collect();
+
+    let mut sets = DisjointSet::new(images.len());
+    for (a, b) in pairs {
+        sets.union(a, b);
+    }
+
+    let mut members: HashMap<usize, Vec<(SimilarImage, u64)>> = HashMap::new();
+    for (index, image) in images.into_iter().enumerate() {
+        members.entry(sets.find(index)).or_default().push(image);
+    }
+
+    let mut clusters: Vec<ImageCluster> = members
+        .into_values()
+        .filter(|images| images.len() > 1)
+        .flat_map(|images| split_by_keeper(images, max_distance))
+        .collect();
+    clusters.sort_by_key(|cluster| Reverse(cluster.reclaimable_bytes));
+
+    let failed = failed.load(Ordering::Relaxed);
+    if failed > 0 {
+        warnings.push(format!("{} 张图片无法解码，已跳过", failed));
+    }
+
+    Ok(SimilarImageScanResult { clusters, warnings })
+}
+
+// ==================== 哈希缓存 (跨扫描持久化) ====================
+
+// 缓存文件: <缓存目录>/CleanDir/hash_cache.jsonl
+// 第一行是版本头，之后每行一条记录，同一文件身份以最后出现的为准
+// 保存时只追加有变化的条目，记录行数超过存活条目两倍时才整体重写
+const HASH_CACHE_FILE: &str = "hash_cache.jsonl";
+const HASH_CACHE_VERSION: u32 = 1;
+// 超过这么多天未被命中的条目在重写时丢弃，避免缓存无限增长
+const HASH_CACHE_MAX_IDLE_DAYS: u64 = 90;
+// 命中时间每天最多记录一次，否则每次扫描都要把命中的条目重新追加一遍
+const HASH_CACHE_TOUCH_INTERVAL: u64 = 86400;
+// 冗余行数少于这个值时不重写，小缓存不必频繁整理
+const HASH_CACHE_MIN_COMPACT_LINES: usize = 10000;
+
+// 文件身份: 任一字段变化都视为内容可能已改变
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+struct FileIdentity {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    ctime: i64,
+    ctime_nsec: i64,
+}
+
+impl FileIdentity {
+    fn from_metadata(metadata: &fs::Metadata) -> Self {
+        FileIdentity {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            ctime: metadata.ctime(),
+            ctime_nsec: metadata.ctime_nsec(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct CachedHashes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full: Option<String>,
+    last_used: u64, // Unix 秒
+}
+
+#[derive(Serialize, Deserialize)]
+struct HashCacheHeader {
+    version: u32,
+}
+
+#[derive(Default)]
+struct HashCacheState {
+    entries: HashMap<FileIdentity, CachedHashes>,
+    changed: HashSet<FileIdentity>, // 自上次保存以来需要写入的条目
+    journal_lines: usize,           // 文件中的记录行数 (含已被覆盖的旧记录)
+    needs_rewrite: bool,            // 文件缺失、损坏或版本不符，下次保存时整体重写
+}
+
+impl HashCacheState {
+    // 只有带完整哈希的条目才会持久化，仅有部分哈希的留在内存里
+    fn mark_changed(&mut self, identity: FileIdentity) {
+        if self
+            .entries
+            .get(&identity)
+            .is_some_and(|cached| cached.full.is_some())
+        {
+            self.changed.insert(identity);
+        }
+    }
+
+    fn touch(&mut self, identity: &FileIdentity) {
+        let now = unix_seconds(SystemTime::now());
+        if let Some(cached) = self.entries.get_mut(identity) {
+            if now.saturating_sub(cached.last_used) >= HASH_CACHE_TOUCH_INTERVAL {
+                cached.last_used = now;
+                self.mark_changed(*identity);
+            }
+        }
+    }
+}
+
+struct HashCache {
+    path: Option<PathBuf>, // 缓存文件，None 时只在内存中缓存
+    state: Mutex<HashCacheState>,
+    save_lock: Mutex<()>, // 多个扫描同时结束时串行保存
+}
+
+lazy_static! {
+    static ref HASH_CACHE: HashCache = HashCache::load();
+}
+
+fn hash_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("CleanDir").join(HASH_CACHE_FILE))
+}
+
+impl HashCache {
+    fn load() -> Self {
+        Self::load_from(hash_cache_path())
+    }
+
+    fn load_from(path: Option<PathBuf>) -> Self {
+        let mut state = HashCacheState {
+            needs_rewrite: true,
+            ..Default::default()
+        };
+        let data = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .unwrap_or_default();
+        let mut lines = data.split(|&b| b == b'\n').filter(|line| !line.is_empty());
+        let header = lines
+            .next()
+            .and_then(|line| serde_json::from_slice::<HashCacheHeader>(line).ok());
+
+        // 版本不符时丢弃旧缓存
+        if header.is_some_and(|header| header.version == HASH_CACHE_VERSION) {
+            state.needs_rewrite = false;
+            let oldest =
+                unix_seconds(SystemTime::now()).saturating_sub(HASH_CACHE_MAX_IDLE_DAYS * 86400);
+            for line in lines {
+                state.journal_lines += 1;
+                // 中途退出可能留下写了一半的行，解析失败的直接跳过
+                if let Ok((identity, cached)) =
+                    serde_json::from_slice::<(FileIdentity, CachedHashes)>(line)
+                {
+                    if cached.last_used >= oldest {
+                        state.entries.insert(identity, cached);
+                    } else {
+                        state.entries.remove(&identity);
+                    }
+                }
+            }
+        }
+
+        HashCache {
+            path,
+            state: Mutex::new(state),
+            save_lock: Mutex::new(()),
+        }
+    }
+
+    fn get_partial(&self, identity: &FileIdentity) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let hash = state.entries.get(identity)?.partial.clone()?;
+        state.touch(identity);
+        Some(hash)
+    }
+
+    fn get_full(&self, identity: &FileIdentity) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let hash = state.entries.get(identity)?.full.clone()?;
+        state.touch(identity);
+        Some(hash)
+    }
+
+    fn put_partial(&self, identity: FileIdentity, hash: String) {
+        let mut state = self.state.lock().unwrap();
+        let cached = state.entries.entry(identity).or_default();
+        cached.partial = Some(hash);
+        cached.last_used = unix_seconds(SystemTime::now());
+        state.mark_changed(identity);
+    }
+
+    fn put_full(&self, identity: FileIdentity, hash: String) {
+        let mut state = self.state.lock().unwrap();
+        let cached = state.entries.entry(identity).or_default();
+        cached.full = Some(hash);
+        cached.last_used = unix_seconds(SystemTime::now());
+        state.mark_changed(identity);
+    }
+
+    // 写入磁盘: 平时只追加变化的条目；需要整理时写到唯一的临时文件再改名
+    fn save(&self) -> std::io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let _guard = self.save_lock.lock().unwrap();
+
+        let (rewrite, records) = {
+            let mut state = self.state.lock().unwrap();
+            if state.changed.is_empty() && !state.needs_rewrite {
+                return Ok(());
+            }
+            let changed = std::mem::take(&mut state.changed);
+            let live = state.entries.values().filter(|c| c.full.is_some()).count();
+            let rewrite = state.needs_rewrite
+                || state.journal_lines + changed.len() > live * 2 + HASH_CACHE_MIN_COMPACT_LINES;
+
+            let records: Vec<(FileIdentity, CachedHashes)> = if rewrite {
+                let oldest = unix_seconds(SystemTime::now())
+                    .saturating_sub(HASH_CACHE_MAX_IDLE_DAYS * 86400);
+                state.entries.retain(|_, cached| cached.last_used >= oldest);
+                state
+                    .entries
+                    .iter()
+                    .filter(|(_, cached)| cached.full.is_some())
+                    .map(|(identity, cached)| (*identity, cached.clone()))
+                    .collect()
+            } else {
+                changed
+                    .iter()
+                    .filter_map(|identity| {
+                        let cached = state.entries.get(identity)?;
+                        Some((*identity, cached.clone()))
+                    })
+                    .collect()
+            };
+            state.journal_lines = if rewrite {
+                records.len()
+            } else {
+                state.journal_lines + records.len()
+            };
+            state.needs_rewrite = false;
+            (rewrite, records)
+        };
+
+        let result = Self::write_records(path, rewrite, &records);
+        if result.is_err() {
+            // 写入失败时文件状态未知，下次整体重写
+            self.state.lock().unwrap().needs_rewrite = true;
+        }
+        result
+    }
+
+    fn write_records(
+        path: &Path,
+        rewrite: bool,
+        records: &[(FileIdentity, CachedHashes)],
+    ) -> std::io::Result<()> {
+        let mut buf = Vec::new();
+        if rewrite {
+            serde_json::to_writer(
+                &mut buf,
+                &HashCacheHeader {
+                    version: HASH_CACHE_VERSION,
+                },
+            )?;
+        }
+        // 每条记录前都先换行，即使上次在行中间被打断也不会和新记录粘在一起
+        for record in records {
+            buf.push(b'\n');
+            serde_json::to_writer(&mut buf, record)?;
+        }
+        buf.push(b'\n');
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if rewrite {
+            let tmp = sibling_temp_path(path, "tmp");
+            fs::write(&tmp, &buf)?;
+            fs::rename(&tmp, path).inspect_err(|_| {
+                let _ = fs::remove_file(&tmp);
+            })
+        } else {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(&buf)
+        }
+    }
+
+    fn clear(&self) -> std::io::Result<()> {
+        let _guard = self.save_lock.lock().unwrap();
+        *self.state.lock().unwrap() = HashCacheState {
+            needs_rewrite: true,
+            ..Default::default()
+        };
+        match &self.path {
+            Some(path) if path.exists() => fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+}
+
+// 清空哈希缓存
+#[tauri::command]
+fn clear_hash_cache() -> Result<(), String> {
+    HASH_CACHE
+        .clear()
+        .map_err(|e| format!("清除哈希缓存失败: {}", e))
+}
+
+// ==================== 重复检测流水线 ====================
+
+// 待比较的文件
+#[derive(Debug, Clone)]
+struct HashCandidate {
+    path: PathBuf,
+    size: u64,
+    dev: u64,               // 所在设备，用于限制单盘并发
+    identity: FileIdentity, // 哈希缓存的键
+    links: Vec<PathBuf>,    // 指向同一 inode 的其他路径
+}
+
+impl HashCandidate {
+    fn new(path: PathBuf, metadata: &fs::Metadata) -> Self {
+        HashCandidate {
+            path,
+            size: metadata.len(),
+            dev: metadata.dev(),
+            identity: FileIdentity::from_metadata(metadata),
+            links: Vec::new(),
+        }
+    }
+}
+
+// 每块盘默认的并发读取线程数 (机械盘可调低到 1-2，NVMe 可适当调高)
+const DEFAULT_IO_THREADS_PER_DEVICE: usize = 4;
+
+// 按设备分组处理文件: 每个设备使用独立的小线程池，同一块盘最多 threads_per_device 个线程同时读取
+// 不在全局 rayon 线程池里阻塞等待，避免拖慢同时进行的其他扫描
+fn run_per_device<R, F>(candidates: Vec<HashCandidate>, threads_per_device: usize, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(HashCandidate) -> Option<R> + Sync,
+{
+    use rayon::prelude::*;
+
+    let mut by_device: HashMap<u64, Vec<HashCandidate>> = HashMap::new();
+    for candidate in candidates {
+        by_device.entry(candidate.dev).or_default().push(candidate);
+    }
+
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = by_device
+            .into_values()
+            .map(|candidates| {
+                scope.spawn(move || {
+                    match rayon::ThreadPoolBuilder::new()
+                        .num_threads(threads_per_device.max(1))
+                        .build()
+                    {
+                        Ok(pool) => {
+                            pool.install(|| candidates.into_par_iter().filter_map(f).collect())
+                        }
+                        // 线程池创建失败时顺序处理
+                        Err(_) => candidates.into_iter().filter_map(f).collect::<Vec<R>>(),
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("哈希线程异常退出"))
+            .collect()
+    })
+}
+
+// 哈希阶段的进度 (按字节计算)
+#[derive(Debug, Serialize, Clone)]
+struct HashProgress {
+    percent: u8,
+    current: usize, // 已处理文件数
+    total: usize,
+    bytes_hashed: u64,
+    total_bytes: u64,
+    current_item: String,
+    elapsed_seconds: u64,
+    estimated_remaining_seconds: u64,
+}
+
+// 进度事件发送间隔
+const HASH_PROGRESS_INTERVAL_MS: u64 = 200;
+
+// 多线程共享的进度计数器
+// 被部分哈希排除的文件直接计入已处理字节，保证进度单调到达 100%
+struct HashProgressTracker<'a> {
+    window: Option<&'a Window>, // None 时不发送进度 (测试中没有窗口)
+    total_files: usize,
+    total_bytes: u64,
+    files_done: AtomicUsize,
+    bytes_done: AtomicU64,
+    start: Instant,
+    last_emit_ms: AtomicU64,
+}
+
+impl<'a> HashProgressTracker<'a> {
+    fn new(window: Option<&'a Window>, candidates: &[HashCandidate]) -> Self {
+        HashProgressTracker {
+            window,
+            total_files: candidates.len(),
+            total_bytes: candidates.iter().map(|c| c.size).sum(),
+            files_done: AtomicUsize::new(0),
+            bytes_done: AtomicU64::new(0),
+            start: Instant::now(),
+            last_emit_ms: AtomicU64::new(0),
+        }
+    }
+
+    // 文件读取了 n 字节
+    fn add_bytes(&self, n: u64, current: &Path) {
+        self.bytes_done.fetch_add(n, Ordering::Relaxed);
+        self.maybe_emit(current);
+    }
+
+    // 文件处理完毕；skipped_bytes 为未读取但已无需处理的字节数
+    fn finish_file(&self, skipped_bytes: u64, current: &Path) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        self.add_bytes(skipped_bytes, current);
+    }
+
+    fn maybe_emit(&self, current: &Path) {
+        let now_ms = self.start.elapsed().as_millis() as u64;
+        let last = self.last_emit_ms.load(Ordering::Relaxed);
+        if now_ms < last + HASH_PROGRESS_INTERVAL_MS {
+            return;
+        }
+        // 只让一个线程发送
+        if self
+            .last_emit_ms
+            .compare_exchange(last, now_ms, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let name = current
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        self.emit(name);
+    }
+
+    fn emit(&self, current_item: String) {
+        let window = match self.window {
+            Some(window) => window,
+            None => return,
+        };
+        let bytes_done = self.bytes_done.load(Ordering::Relaxed).min(self.total_bytes);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let speed = if elapsed > 0.0 {
+            bytes_done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let remaining = if speed > 0.0 {
+            ((self.total_bytes - bytes_done) as f64 / speed) as u64
+        } else {
+            0
+        };
+        let percent = if self.total_bytes > 0 {
+            (bytes_done as f64 / self.total_bytes as f64 * 100.0) as u8
+        } else {
+            100
+        };
+
+        let _ = window.emit(
+            "scan-progress",
+            HashProgress {
+                percent,
+                current: self.files_done.load(Ordering::Relaxed),
+                total: self.total_files,
+                bytes_hashed: bytes_done,
+                total_bytes: self.total_bytes,
+                current_item,
+                elapsed_seconds: elapsed as u64,
+                estimated_remaining_seconds: remaining,
+            },
+        );
+    }
+}
+
+// 大小 -> 部分哈希 -> 全量哈希，返回 (全量哈希, 成员) 列表，每组至少两个不同的 inode
+// cache 为 Some 时复用并更新持久化的哈希缓存
+fn find_duplicate_groups(
+    files: Vec<HashCandidate>,
+    window: Option<&Window>,
+    cache: Option<&HashCache>,
+    io_threads_per_device: usize,
+) -> Vec<(String, Vec<HashCandidate>)> {
+    use std::collections::hash_map::Entry;
+
+    // 同一 inode 的多个路径 (硬链接) 合并为一个候选，只哈希一次，也不算作重复
+    let mut inodes: HashMap<(u64, u64), HashCandidate> = HashMap::new();
+    for file in files {
+        match inodes.entry((file.dev, file.identity.ino)) {
+            Entry::Occupied(mut existing) => {
+                // 以字典序最小的路径作为代表，保证结果稳定
+                let existing = existing.get_mut();
+                if file.path < existing.path {
+                    let previous = std::mem::replace(&mut existing.path, file.path);
+                    existing.links.push(previous);
+                } else {
+                    existing.links.push(file.path);
+                }
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(file);
+            }
+        }
+    }
+
+    // 第一步: 按文件大小分组，只保留有多个 inode 的组 (跳过空文件)
+    let mut size_groups: HashMap<u64, Vec<HashCandidate>> = HashMap::new();
+    for file in inodes.into_values() {
+        if file.size > 0 {
+            size_groups.entry(file.size).or_default().push(file);
+        }
+    }
+    let candidates: Vec<HashCandidate> = size_groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .flatten()
+        .collect();
+
+    let progress = HashProgressTracker::new(window, &candidates);
+
+    // 第二步: 并行计算部分哈希 (前 4KB + 后 4KB)
+    let partial: Vec<(HashCandidate, String)> =
+        run_per_device(candidates, io_threads_per_device, |candidate| {
+            let cached = cache.and_then(|c| c.get_partial(&candidate.identity));
+            let result = match cached {
+                Some(hash) => Ok(hash),
+                None => {
+                    let result = calculate_partial_hash(&candidate.path);
+                    if let (Some(cache), Ok(hash)) = (cache, &result) {
+                        cache.put_partial(candidate.identity, hash.clone());
+                    }
+                    result
+                }
+            };
+            match result {
+                Ok(hash) => Some((candidate, hash)),
+                Err(_) => {
+                    progress.finish_file(candidate.size, &candidate.path);
+                    None
+                }
+            }
+        });
+
+    // 部分哈希相同只说明头尾一致，还需全量哈希确认
+    let mut partial_groups: HashMap<(u64, String), Vec<HashCandidate>> = HashMap::new();
+    for (candidate, hash) in partial {
+        partial_groups
+            .entry((candidate.size, hash))
+            .or_default()
+            .push(candidate);
+    }
+    let mut full_candidates: Vec<HashCandidate> = Vec::new();
+    for group in partial_groups.into_values() {
+        if group.len() > 1 {
+            full_candidates.extend(group);
+        } else {
+            for candidate in group {
+                progress.finish_file(candidate.size, &candidate.path);
+            }
+        }
+    }
+
+    // 第三步: 并行计算全量哈希
+    let full = hash_files_parallel(full_candidates, io_threads_per_device, &progress, cache);
+
+    progress.emit("完成".to_string());
+
+    // 只保留全量内容一致的组
+    let mut full_groups: HashMap<(u64, String), Vec<HashCandidate>> = HashMap::new();
+    for (candidate, hash) in full {
+        full_groups
+            .entry((candidate.size, hash))
+            .or_default()
+            .push(candidate);
+    }
+    full_groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|((_, hash), members)| (hash, members))
+        .collect()
+}
+
+// 并行计算全量哈希 (读取失败的文件直接跳过)
+fn hash_files_parallel(
+    candidates: Vec<HashCandidate>,
+    io_threads_per_device: usize,
+    progress: &HashProgressTracker,
+    cache: Option<&HashCache>,
+) -> Vec<(HashCandidate, String)> {
+    run_per_device(candidates, io_threads_per_device, |candidate| {
+        // 命中缓存时无需读取文件
+        if let Some(hash) = cache.and_then(|c| c.get_full(&candidate.identity)) {
+            progress.finish_file(candidate.size, &candidate.path);
+            return Some((candidate, hash));
+        }
+
+        let result =
+            calculate_full_hash(&candidate.path, &|n| progress.add_bytes(n, &candidate.path));
+        progress.finish_file(0, &candidate.path);
+        let hash = result.ok()?;
+        if let Some(cache) = cache {
+            cache.put_full(candidate.identity, hash.clone());
+        }
+        Some((candidate, hash))
+    })
+}
+
+// 部分哈希读取的头尾长度
+const PARTIAL_HASH_BYTES: u64 = 4096;
+
+// 计算部分哈希 (前 4KB + 后 4KB)，用于快速排除
+fn calculate_partial_hash(path: &Path) -> Result<String, std::io::Error> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = fs::File::open(path)?;
+    let metadata = file.metadata()?;
+    let file_size = metadata.len();
+
+    let mut hasher = blake3::Hasher::new();
+
+    // 读取开头
+    let mut buffer = vec![0u8; PARTIAL_HASH_BYTES.min(file_size) as usize];
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+
+    // 如果文件大于头尾之和,再读取结尾
+    if file_size > PARTIAL_HASH_BYTES * 2 {
+        file.seek(SeekFrom::End(-(PARTIAL_HASH_BYTES as i64)))?;
+        file.read_exact(&mut buffer)?;
+        hasher.update(&buffer);
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// 计算全量内容哈希 (BLAKE3)，作为重复判定的最终依据
+// on_read 在每次读取后回调已读字节数，用于进度统计
+fn calculate_full_hash(path: &Path, on_read: &dyn Fn(u64)) -> Result<String, std::io::Error> {
+    use std::io::Read;
+
+    let mut file = fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; 128 * 1024];
+
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        on_read(n as u64);
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// ==================== 空文件和空目录 ====================
+
+// 默认忽略的占位文件: 只含这些文件的目录仍视为空目录
+const DEFAULT_EMPTY_MARKERS: &[&str] = &[".gitkeep", ".keep"];
+
+struct EmptyScanOptions {
+    markers: HashSet<String>,
+    enable_smart_filter: bool,
+}
+
+// 递归检查目录，返回 Some(目录内条目数) 表示目录为空 (只含空目录和占位文件)
+// 目录不为空时 (或是扫描根目录)，把其中最上层的空目录写入 items；空文件总是写入
+fn collect_empty_entries(
+    dir: &Path,
+    is_root: bool,
+    options: &EmptyScanOptions,
+    ignore_rules: &IgnoreStack,
+    items: &mut Vec<DiskItem>,
+) -> Option<usize> {
+    // 读不了的目录按非空处理，避免误报
+    let entries = fs::read_dir(dir).ok()?;
+    let ignore_rules = ignore_rules.with_dir(dir);
+
+    let mut is_empty = true;
+    let mut entry_count = 0;
+    let mut empty_dirs: Vec<(PathBuf, usize)> = Vec::new();
+    for entry in entries {
+        let (entry, file_type) = match entry.and_then(|e| e.file_type().map(|t| (e, t))) {
+            Ok(pair) => pair,
+            Err(_) => {
+                is_empty = false;
+                continue;
+            }
+        };
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // 受 .cleandirignore 保护的条目不报告，所在目录也不能当作空目录删除
+        if ignore_rules.action(&path, file_type.is_dir()).is_some() {
+            is_empty = false;
+            continue;
+        }
+
+        if file_type.is_dir() {
+            // 智能过滤的目录 (如 .git) 不检查，其中的空目录通常有意义
+            if should_skip_path(&path, options.enable_smart_filter) {
+                is_empty = false;
+                continue;
+            }
+            match collect_empty_entries(&path, false, options, &ignore_rules, items) {
+                Some(count) => {
+                    entry_count += count + 1;
+                    empty_dirs.push((path, count));
+                }
+                None => is_empty = false,
+            }
+        } else if options.markers.contains(&name) {
+            entry_count += 1;
+        } else {
+            is_empty = false;
+            let is_empty_file =
+                file_type.is_file() && entry.metadata().map(|m| m.len() == 0).unwrap_or(false);
+            if is_empty_file {
+                items.push(DiskItem {
+                    file_type: get_file_type(&name),
+                    name,
+                    path: path.to_string_lossy().to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    if is_empty && !is_root {
+        return Some(entry_count);
+    }
+    for (path, count) in empty_dirs {
+        items.push(DiskItem {
+            name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path: path.to_string_lossy().to_string(),
+            is_directory: true,
+            item_count: count,
+            file_type: Some("directory".to_string()),
+            ..Default::default()
+        });
+    }
+    if is_empty {
+        Some(entry_count)
+    } else {
+        None
+    }
+}
+
+// 空文件和空目录扫描 (空目录指为空或只含空目录的目录，只报告最上层)
+#[tauri::command]
+async fn scan_empty_items(
+    path: String,
+    ignore_markers: Option<Vec<String>>,
+    enable_smart_filter: Option<bool>,
+) -> Result<ScanResult, String> {
+    let path_obj = Path::new(&path);
+    if !path_obj.is_dir() {
+        return Err("目录不存在".to_string());
+    }
+
+    let options = EmptyScanOptions {
+        markers: ignore_markers
+            .unwrap_or_else(|| {
+                DEFAULT_EMPTY_MARKERS
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect()
+            })
+            .into_iter()
+            .collect(),
+        enable_smart_filter: enable_smart_filter.unwrap_or(true),
+    };
+
+    let mut items = Vec::new();
+    collect_empty_entries(
+        path_obj,
+        true,
+        &options,
+        &IgnoreStack::default(),
+        &mut items,
+    );
+
+    // 空目录在前 (含条目多的优先)，空文件按路径排序
+    items.sort_by(|a, b| {
+        b.is_directory
+            .cmp(&a.is_directory)
+            .then(b.item_count.cmp(&a.item_count))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    Ok(ScanResult {
+        items,
+        ..Default::default()
+    })
+}
+
+// ==================== 失效符号链接 ====================
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum SymlinkProblem {
+    Missing,     // 目标不存在
+    Loop,        // 链接形成循环
+    OutsideRoot, // 目标位于扫描目录之外
+}
+
+#[derive(Debug, Serialize)]
+struct BrokenSymlink {
+    name: String,
+    path: String,
+    target: String, // 链接中记录的原始目标 (未解析)
+    problem: SymlinkProblem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved: Option<String>, // 指向扫描目录外时，解析后的实际路径
+}
+
+// 检查单个符号链接，正常 (目标存在且在扫描目录内) 时返回 None
+fn check_symlink(path: &Path, root: &Path) -> Option<(SymlinkProblem, Option<PathBuf>)> {
+    match fs::metadata(path) {
+        Ok(_) => {
+            let resolved = fs::canonicalize(path).ok()?;
+            if resolved.starts_with(root) {
+                None
+            } else {
+                Some((SymlinkProblem::OutsideRoot, Some(resolved)))
+            }
+        }
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => Some((SymlinkProblem::Loop, None)),
+        Err(e)
+            if e.kind() == std::io::ErrorKind::NotFound
+                || e.raw_os_error() == Some(libc::ENOTDIR) =>
+        {
+            Some((SymlinkProblem::Missing, None))
+        }
+        Err(_) => None, // 权限等问题无法判断，不报告
+    }
+}
+
+// 失效符号链接扫描: 目标缺失、循环链接、指向扫描目录之外的链接
+#[tauri::command]
+async fn scan_broken_symlinks(
+    path: String,
+    enable_smart_filter: Option<bool>,
+) -> Result<Vec<BrokenSymlink>, String> {
+    use walkdir::WalkDir;
+
+    let root = fs::canonicalize(&path).map_err(|e| format!("读取目录失败: {}", e))?;
+    let enable_filter = enable_smart_filter.unwrap_or(true);
+    let mut ignore_rules = IgnoreStack::default();
+
+    let mut links: Vec<BrokenSymlink> = WalkDir::new(&root)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| !should_skip_path(e.path(), enable_filter) && ignore_rules.allows(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path_is_symlink())
+        .filter_map(|entry| {
+            let (problem, resolved) = check_symlink(entry.path(), &root)?;
+            let target = fs::read_link(entry.path()).ok()?;
+            Some(BrokenSymlink {
+                name: entry.file_name().to_string_lossy().to_string(),
+                path: entry.path().to_string_lossy().to_string(),
+                target: target.to_string_lossy().to_string(),
+                problem,
+                resolved: resolved.map(|p| p.to_string_lossy().to_string()),
+            })
+        })
+        .collect();
+
+    links.sort_by(|a, b| a.problem.cmp(&b.problem).then_with(|| a.path.cmp(&b.path)));
+    Ok(links)
+}
+
+// ==================== 开发项目构建产物 ====================
+
+// 项目类型规则: 根目录下出现 markers 之一即视为该类项目
+struct ProjectRule {
+    kind: &'static str,
+    markers: &'static [&'static str],
+    artifacts: &'static [&'static str], // 项目根目录下可重新生成的目录
+    nested_artifacts: &'static [&'static str], // 项目内任意位置可重新生成的目录
+}
+
+const PROJECT_RULES: &[ProjectRule] = &[
+    ProjectRule {
+        kind: "rust",
+        markers: &["Cargo.toml"],
+        artifacts: &["target"],
+        nested_artifacts: &[],
+    },
+    ProjectRule {
+        kind: "node",
+        markers: &["package.json"],
+        artifacts: &["dist", ".next", ".nuxt", ".turbo", ".parcel-cache"],
+        nested_artifacts: &["node_modules"],
+    },
+    ProjectRule {
+        kind: "python",
+        markers: &["pyproject.toml"],
+        artifacts: &[
+            "build",
+            "dist",
+            ".venv",
+            ".tox",
+            ".pytest_cache",
+            ".mypy_cache",
+        ],
+        nested_artifacts: &["__pycache__"],
+    },
+    ProjectRule {
+        kind: "gradle",
+        markers: &["build.gradle", "build.gradle.kts"],
+        // 多模块项目的每个模块都有自己的 build.gradle，会被识别为单独的项目；
+        // build 只在模块根目录下才算产物，避免误伤 com/acme/build 这样的源码包
+        artifacts: &[".gradle", "build"],
+        nested_artifacts: &[],
+    },
+    ProjectRule {
+        kind: "cmake",
+        markers: &["CMakeLists.txt"],
+        artifacts: &["build", "cmake-build-debug", "cmake-build-release"],
+        nested_artifacts: &[],
+    },
+];
+
+#[derive(Debug, Serialize)]
+struct BuildArtifact {
+    name: String,
+    path: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct DevProject {
+    name: String,
+    path: String,
+    kinds: Vec<String>,
+    artifacts: Vec<BuildArtifact>,
+    reclaimable_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_source_modified: Option<u64>, // 源码 (构建产物之外的文件) 最后修改时间 (Unix 秒)
+}
+
+#[derive(Debug, Serialize, Default)]
+struct BuildArtifactScanResult {
+    projects: Vec<DevProject>,
+    total_reclaimable_bytes: u64,
+}
+
+// 遍历过程中发现的项目
+struct ProjectScan {
+    path: PathBuf,
+    rules: Vec<&'static ProjectRule>,
+    artifacts: Vec<PathBuf>,
+    last_source_modified: Option<SystemTime>,
+}
+
+impl ProjectScan {
+    fn is_artifact(&self, parent: &Path, name: &str) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.nested_artifacts.contains(&name)
+                || (parent == self.path && rule.artifacts.contains(&name))
+        })
+    }
+}
+
+// 递归查找项目根目录，构建产物归属到最近的外层项目，不再深入
+fn discover_projects(
+    dir: &Path,
+    enclosing: Option<usize>,
+    ignore_rules: &IgnoreStack,
+    projects: &mut Vec<ProjectScan>,
+) {
+    let entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).collect(),
+        Err(_) => return,
+    };
+    let ignore_rules = ignore_rules.with_dir(dir);
+
+    let rules: Vec<&'static ProjectRule> = PROJECT_RULES
+        .iter()
+        .filter(|rule| {
+            entries
+                .iter()
+                .any(|e| rule.markers.iter().any(|m| e.file_name() == *m))
+        })
+        .collect();
+    let current = if rules.is_empty() {
+        enclosing
+    } else {
+        projects.push(ProjectScan {
+            path: dir.to_path_buf(),
+            rules,
+            artifacts: Vec::new(),
+            last_source_modified: None,
+        });
+        Some(projects.len() - 1)
+    };
+
+    for entry in entries {
+        let file_type = match entry.file_type() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if file_type.is_dir() {
+            if name == ".git" || ignore_rules.action(&entry.path(), true).is_some() {
+                continue;
+            }
+            if let Some(index) = current {
+                if projects[index].is_artifact(dir, &name) {
+                    projects[index].artifacts.push(entry.path());
+                    continue;
+                }
+            }
+            discover_projects(&entry.path(), current, &ignore_rules, projects);
+        } else if file_type.is_file() {
+            if let Some(index) = current {
+                if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                    let project = &mut projects[index];
+                    project.last_source_modified = project.last_source_modified.max(Some(modified));
+                }
+            }
+        }
+    }
+}
+
+// 统计构建产物目录的磁盘占用 (不限深度，硬链接只计一次)
+fn artifact_size(path: &Path, seen_inodes: &InodeSet) -> u64 {
+    use walkdir::WalkDir;
+
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_type().is_dir())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| !is_duplicate_inode(m.dev(), m.ino(), seen_inodes))
+        .map(|m| m.blocks() * 512)
+        .sum()
+}
+
+// 开发项目构建产物扫描: 按项目汇总可重新生成的目录，并给出源码最后修改时间
+#[tauri::command]
+async fn scan_build_artifacts(
+    path: String,
+    window: Window,
+) -> Result<BuildArtifactScanResult, String> {
+    use rayon::prelude::*;
+
+    init_rayon_pool();
+
+    let path_obj = Path::new(&path);
+    if !path_obj.is_dir() {
+        return Err("目录不存在".to_string());
+    }
+
+    let start_time = SystemTime::now();
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 5,
+            current: 0,
+            total: 0,
+            current_item: "正在查找项目...".to_string(),
+            elapsed_seconds: 0,
+            estimated_remaining_seconds: 0,
+        },
+    );
+
+    let mut projects = Vec::new();
+    discover_projects(path_obj, None, &IgnoreStack::default(), &mut projects);
+    projects.retain(|project| !project.artifacts.is_empty());
+
+    // 并行计算所有构建产物的大小
+    let artifacts: Vec<(usize, &PathBuf)> = projects
+        .iter()
+        .enumerate()
+        .flat_map(|(index, project)| project.artifacts.iter().map(move |a| (index, a)))
+        .collect();
+    let total = artifacts.len();
+    let processed = AtomicUsize::new(0);
+    let seen_inodes: InodeSet = Arc::new(Mutex::new(HashSet::new()));
+    let sizes: Vec<(usize, BuildArtifact)> = artifacts
+        .par_iter()
+        .map(|(index, artifact)| {
+            let size = artifact_size(artifact, &seen_inodes);
+
+            let curr = processed.fetch_add(1, Ordering::Relaxed) + 1;
+            let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
+            let speed = if elapsed > 0 {
+                curr as f64 / elapsed as f64
+            } else {
+                0.0
+            };
+            let remaining = if speed > 0.0 && total > curr {
+                ((total - curr) as f64 / speed) as u64
+            } else {
+                0
+            };
+            let _ = window.emit(
+                "scan-progress",
+                ScanProgress {
+                    percent: (10 + (curr * 85 / total.max(1))) as u8,
+                    current: curr,
+                    total,
+                    current_item: artifact.to_string_lossy().to_string(),
+                    elapsed_seconds: elapsed,
+                    estimated_remaining_seconds: remaining,
+                },
+            );
+
+            (
+                *index,
+                BuildArtifact {
+                    name: artifact
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                    path: artifact.to_string_lossy().to_string(),
+                    size,
+                },
+            )
+        })
+        .collect();
+
+    let mut grouped: Vec<Vec<BuildArtifact>> = projects.iter().map(|_| Vec::new()).collect();
+    for (index, artifact) in sizes {
+        grouped[index].push(artifact);
+    }
+
+    let mut result = BuildArtifactScanResult::default();
+    for (project, mut artifacts) in projects.iter().zip(grouped) {
+        artifacts.sort_by_key(|a| Reverse(a.size));
+        let reclaimable_bytes = artifacts.iter().map(|a| a.size).sum();
+        result.total_reclaimable_bytes += reclaimable_bytes;
+        result.projects.push(DevProject {
+            name: project
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path: project.path.to_string_lossy().to_string(),
+            kinds: project.rules.iter().map(|r| r.kind.to_string()).collect(),
+            artifacts,
+            reclaimable_bytes,
+            last_source_modified: project.last_source_modified.map(unix_seconds),
+        });
+    }
+    result
+        .projects
+        .sort_by_key(|p| Reverse(p.reclaimable_bytes));
+
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 100,
+            current: total,
+            total,
+            current_item: "完成".to_string(),
+            elapsed_seconds: start_time.elapsed().unwrap_or_default().as_secs(),
+            estimated_remaining_seconds: 0,
+        },
+    );
+
+    Ok(result)
+}
+
+// ==================== 工具缓存清单 ====================
+
+const TOOL_CACHES_CONFIG: &str = "tool_caches.json";
+
+// 内置的已知缓存位置 (编号, 所属工具, 路径, 安全清理方式)，用户可在配置文件中追加或按编号覆盖
+const BUILTIN_TOOL_CACHES: &[(&str, &str, &str, &str)] = &[
+    (
+        "cargo-registry",
+        "cargo",
+        "~/.cargo/registry",
+        "cargo cache --autoclean (需安装 cargo-cache)，或删除后由 cargo 重新下载",
+    ),
+    (
+        "rustup-toolchains",
+        "rustup",
+        "~/.rustup/toolchains",
+        "rustup toolchain list 查看后，用 rustup toolchain uninstall <工具链> 卸载不用的版本",
+    ),
+    (
+        "npm-cache",
+        "npm",
+        "~/.npm/_cacache",
+        "npm cache clean --force",
+    ),
+    ("pip-cache", "pip", "~/.cache/pip", "pip cache purge"),
+    (
+        "maven-repository",
+        "maven",
+        "~/.m2/repository",
+        "mvn dependency:purge-local-repository，或删除后由 Maven 重新下载",
+    ),
+    (
+        "gradle-caches",
+        "gradle",
+        "~/.gradle/caches",
+        "先执行 gradle --stop，再删除该目录",
+    ),
+    (
+        "go-build-cache",
+        "go",
+        "~/.cache/go-build",
+        "go clean -cache",
+    ),
+    (
+        "huggingface-cache",
+        "huggingface",
+        "~/.cache/huggingface",
+        "huggingface-cli delete-cache",
+    ),
+];
+
+// 缓存位置规则 (配置文件格式与之相同)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ToolCacheRule {
+    id: String,
+    tool: String,
+    path: String, // 支持 ~/ 开头表示主目录
+    cleanup: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolCache {
+    id: String,
+    tool: String,
+    path: String,
+    size: u64,
+    file_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<u64>, // 缓存中最新文件的修改时间 (Unix 秒)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_days: Option<u64>, // 距最新文件修改的天数
+    cleanup: String,
+}
+
+fn load_tool_cache_rules() -> Vec<ToolCacheRule> {
+    let mut rules: Vec<ToolCacheRule> = BUILTIN_TOOL_CACHES
+        .iter()
+        .map(|(id, tool, path, cleanup)| ToolCacheRule {
+            id: id.to_string(),
+            tool: tool.to_string(),
+            path: path.to_string(),
+            cleanup: cleanup.to_string(),
+        })
+        .collect();
+
+    let config_path = match app_config_dir() {
+        Some(dir) => dir.join(TOOL_CACHES_CONFIG),
+        None => return rules,
+    };
+    let overrides: Vec<ToolCacheRule> = match fs::read_to_string(&config_path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            println!(
+                "警告：工具缓存配置解析失败 {}: {}",
+                config_path.display(),
+                e
+            );
+            Vec::new()
+        }),
+        Err(_) => Vec::new(), // 配置文件不存在时使用内置表
+    };
+
+    // 用户配置按编号覆盖内置规则，新编号追加在后面
+    for rule in overrides {
+        match rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => *existing = rule,
+            None => rules.push(rule),
+        }
+    }
+    rules
+}
+
+fn expand_home(path: &str) -> Option<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+        None => Some(PathBuf::from(path)),
+    }
+}
+
+// 工具缓存清单: 只报告本机存在的缓存目录，按大小降序
+#[tauri::command]
+async fn scan_tool_caches() -> Result<Vec<ToolCache>, String> {
+    use rayon::prelude::*;
+    use walkdir::WalkDir;
+
+    init_rayon_pool();
+
+    let now = SystemTime::now();
+    let seen_inodes: InodeSet = Arc::new(Mutex::new(HashSet::new()));
+    let mut caches: Vec<ToolCache> = load_tool_cache_rules()
+        .into_par_iter()
+        .filter_map(|rule| {
+            let path = expand_home(&rule.path)?;
+            if !path.is_dir() {
+                return None;
+            }
+
+            let mut size = 0;
+            let mut file_count = 0;
+            let mut newest: Option<SystemTime> = None;
+            for metadata in WalkDir::new(&path)
+                .follow_links(false)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .filter_map(|e| e.metadata().ok())
+            {
+                if is_duplicate_inode(metadata.dev(), metadata.ino(), &seen_inodes) {
+                    continue;
+                }
+                size += metadata.blocks() * 512;
+                file_count += 1;
+                newest = newest.max(metadata.modified().ok());
+            }
+
+            Some(ToolCache {
+                id: rule.id,
+                tool: rule.tool,
+                path: path.to_string_lossy().to_string(),
+                size,
+                file_count,
+                last_modified: newest.map(unix_seconds),
+                age_days: newest.map(|time| {
+                    now.duration_since(time).unwrap_or_default().as_secs() / (24 * 60 * 60)
+                }),
+                cleanup: rule.cleanup,
+            })
+        })
+        .collect();
+
+    caches.sort_by_key(|c| Reverse(c.size));
+    Ok(caches)
+}
+
+// ==================== Git 仓库分析 ====================
+
+#[derive(Debug, Serialize, Default)]
+struct GitStorage {
+    total: u64,
+    packs: u64,         // objects/pack 下的 pack 与索引
+    loose_objects: u64, // objects 下的松散对象
+    lfs: u64,           // lfs 对象缓存
+    other: u64,         // 索引、日志、钩子等
+}
+
+#[derive(Debug, Serialize, Default)]
+struct GitRepoReport {
+    name: String,
+    path: String,
+    git_dir: String,
+    storage: GitStorage,
+    tracked_bytes: u64,
+    tracked_files: usize,
+    untracked_bytes: u64,
+    untracked_files: usize,
+    ignored_bytes: u64, // 被 .gitignore / info/exclude / 全局忽略规则命中
+    ignored_files: usize,
+    total_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>, // 索引读取失败时，已跟踪文件会被计为未跟踪
+}
+
+// 解析工作树对应的 git 目录 (.git 目录，或 worktree / 子模块的 "gitdir: ..." 文件)
+fn resolve_git_dir(worktree: &Path) -> Option<PathBuf> {
+    let dot_git = worktree.join(".git");
+    let metadata = dot_git.symlink_metadata().ok()?;
+    if metadata.is_dir() {
+        return Some(dot_git);
+    }
+
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let target = content
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))?
+        .trim();
+    Some(worktree.join(target))
+}
+
+// 仓库使用的对象哈希长度 (SHA-1 为 20 字节，SHA-256 为 32 字节)
+fn git_hash_len(git_dir: &Path) -> usize {
+    let config = fs::read_to_string(git_dir.join("config")).unwrap_or_default();
+    let is_sha256 = config.lines().any(|line| {
+        let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+        line.eq_ignore_ascii_case("objectformat=sha256")
+    });
+    if is_sha256 {
+        32
+    } else {
+        20
+    }
+}
+
+// git 索引 v4 路径前缀压缩使用的变长整数 (与 git 的 decode_varint 一致)
+fn read_index_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut used = 0;
+    let mut byte = *data.first()?;
+    used += 1;
+    let mut value = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        byte = *data.get(used)?;
+        used += 1;
+        value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+    }
+    Some((value, used))
+}
+
+// 解码 EWAH 压缩位图 (git 的 ewah/ewah_io.c 格式)，返回置位的序号
+fn read_ewah_bitmap(data: &[u8]) -> Option<Vec<usize>> {
+    // 头部记录位图的总位数，超出的位 (包括伪造的超长连续段) 一律忽略
+    let bit_size = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+    let word_count = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+    let word = |i: usize| -> Option<u64> {
+        let at = 8 + i * 8;
+        Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+    };
+
+    let mut bits = Vec::new();
+    let mut position = 0;
+    let mut i = 0;
+    while i < word_count {
+        // 标记字: 最低位为连续段的取值，接着 32 位为连续的字数，最高 31 位为其后的字面字数
+        let marker = word(i)?;
+        i += 1;
+        let running_words = ((marker >> 1) & 0xffff_ffff) as usize;
+        if marker & 1 != 0 {
+            bits.extend(position..(position + running_words * 64).min(bit_size));
+        }
+        position += running_words * 64;
+        for _ in 0..(marker >> 33) {
+            let literal = word(i)?;
+            i += 1;
+            bits.extend(
+                (0..64)
+                    .filter(|bit| literal >> bit & 1 != 0)
+                    .map(|bit| position + bit)
+                    .filter(|&bit| bit < bit_size),
+            );
+            position += 64;
+        }
+    }
+    Some(bits)
+}
+
+// 拆分索引 (core.splitIndex) 的 link 扩展: 大部分条目存放在 sharedindex.<hash> 中
+struct SplitIndexLink {
+    shared_hash: String, // 十六进制，全 0 表示没有共享索引
+    deleted: Vec<usize>, // 共享索引中已被删除的条目序号
+}
+
+fn parse_split_index_link(body: &[u8], hash_len: usize) -> Result<SplitIndexLink, String> {
+    let invalid = || "Git 拆分索引的 link 扩展无效".to_string();
+    let hash = body.get(..hash_len).ok_or_else(invalid)?;
+    // 哈希之后依次是删除位图和替换位图；被替换的条目路径取自共享索引，只需处理删除
+    let deleted = match &body[hash_len..] {
+        [] => Vec::new(),
+        bitmaps => read_ewah_bitmap(bitmaps).ok_or_else(invalid)?,
+    };
+    Ok(SplitIndexLink {
+        shared_hash: hash.iter().map(|b| format!("{:02x}", b)).collect(),
+        deleted,
+    })
+}
+
+// 解析后的索引: 所有条目的相对路径，以及拆分索引的 link 扩展
+struct GitIndex {
+    paths: Vec<Vec<u8>>,
+    link: Option<SplitIndexLink>,
+}
+
+// 直接解析 .git/index (版本 2-4)
+fn parse_git_index(data: &[u8], hash_len: usize) -> Result<GitIndex, String> {
+    let truncated = || "Git 索引文件已截断".to_string();
+    let read_u32 = |at: usize| -> Result<u32, String> {
+        data.get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(truncated)
+    };
 
-    // 按大小降序排序
-    let mut items = items;
-    items.sort_by(|a, b| b.size.cmp(&a.size));
+    if data.get(0..4) != Some(b"DIRC".as_slice()) {
+        return Err("不是有效的 Git 索引文件".to_string());
+    }
+    let version = read_u32(4)?;
+    if !(2..=4).contains(&version) {
+        return Err(format!("不支持的 Git 索引版本: {}", version));
+    }
+    let count = read_u32(8)? as usize;
+
+    // 条目数来自文件头，按每个条目的最小长度限制预分配，避免伪造的计数耗尽内存
+    let mut paths: Vec<Vec<u8>> = Vec::with_capacity(count.min(data.len() / (42 + hash_len)));
+    let mut offset = 12;
+    for _ in 0..count {
+        let entry_start = offset;
+        // 10 个 32 位字段 (时间、设备、inode、权限、属主、大小) + 对象哈希 + 标志位
+        let flags_at = entry_start + 40 + hash_len;
+        let flags = data
+            .get(flags_at..flags_at + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(truncated)?;
+        offset = flags_at + 2;
+        if version >= 3 && flags & 0x4000 != 0 {
+            offset += 2; // 扩展标志位
+        }
+
+        let path = if version == 4 {
+            // 路径 = 上一条路径去掉末尾 strip 字节 + 本条后缀
+            let (strip, used) = read_index_varint(data.get(offset..).ok_or_else(truncated)?)
+                .ok_or_else(truncated)?;
+            offset += used;
+            let previous = paths.last().map(|p| p.as_slice()).unwrap_or_default();
+            let keep = previous.len().checked_sub(strip).ok_or_else(truncated)?;
+            let end = offset
+                + data
+                    .get(offset..)
+                    .and_then(|rest| rest.iter().position(|&b| b == 0))
+                    .ok_or_else(truncated)?;
+            let mut path = previous[..keep].to_vec();
+            path.extend_from_slice(&data[offset..end]);
+            offset = end + 1;
+            path
+        } else {
+            let end = offset
+                + data
+                    .get(offset..)
+                    .and_then(|rest| rest.iter().position(|&b| b == 0))
+                    .ok_or_else(truncated)?;
+            let path = data[offset..end].to_vec();
+            // 条目以 1-8 个 NUL 补齐到 8 字节对齐
+            offset = entry_start + (end - entry_start + 8) / 8 * 8;
+            path
+        };
+        paths.push(path);
+    }
 
-    Ok(ScanResult { items })
+    // 条目之后是扩展 (4 字节签名 + 4 字节长度 + 内容)，最后是校验和
+    let mut link = None;
+    let extensions_end = data.len().saturating_sub(hash_len);
+    while offset + 8 <= extensions_end {
+        let size = read_u32(offset + 4)? as usize;
+        let body = data
+            .get(offset + 8..offset + 8 + size)
+            .ok_or_else(truncated)?;
+        if &data[offset..offset + 4] == b"link" {
+            link = Some(parse_split_index_link(body, hash_len)?);
+        }
+        offset += 8 + size;
+    }
+    Ok(GitIndex { paths, link })
 }
 
-// 旧文件扫描 (扫描超过指定天数未修改的文件)
-#[tauri::command]
-async fn scan_old_files(
-    path: String,
-    days_threshold: u64,
-    window: Window,
-) -> Result<ScanResult, String> {
-    use rayon::prelude::*;
-    use walkdir::WalkDir;
+// 读取已跟踪的文件 (稀疏索引中的目录条目以 / 结尾，整个目录视为已跟踪)
+fn read_tracked_paths(
+    worktree: &Path,
+    git_dir: &Path,
+) -> Result<(HashSet<PathBuf>, Vec<PathBuf>), String> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
 
-    init_rayon_pool();
+    let data = match fs::read(git_dir.join("index")) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(), // 新仓库还没有索引
+        Err(e) => return Err(format!("读取 Git 索引失败: {}", e)),
+    };
+    if data.is_empty() {
+        return Ok((HashSet::new(), Vec::new()));
+    }
 
-    let path_obj = Path::new(&path);
-    let now = SystemTime::now();
-    let threshold_duration = std::time::Duration::from_secs(days_threshold * 24 * 60 * 60);
+    let hash_len = git_hash_len(git_dir);
+    let index = parse_git_index(&data, hash_len)?;
+    let mut paths = index.paths;
 
-    let start_time = SystemTime::now();
-    let current = Arc::new(AtomicUsize::new(0));
+    // 拆分索引: 本文件只记录改动 (替换共享条目的条目路径为空)，其余条目在共享索引中
+    let link = index
+        .link
+        .filter(|link| link.shared_hash.bytes().any(|b| b != b'0'));
+    if let Some(link) = link {
+        let shared_path = git_dir.join(format!("sharedindex.{}", link.shared_hash));
+        let shared = fs::read(&shared_path)
+            .map_err(|e| format!("读取 Git 共享索引 {} 失败: {}", shared_path.display(), e))?;
+        let deleted: HashSet<usize> = link.deleted.into_iter().collect();
+        let shared_paths = parse_git_index(&shared, hash_len)?.paths;
+        paths.extend(
+            shared_paths
+                .into_iter()
+                .enumerate()
+                .filter(|(position, _)| !deleted.contains(position))
+                .map(|(_, path)| path),
+        );
+    }
 
-    // 遍历所有文件
-    let all_files: Vec<_> = WalkDir::new(path_obj)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .collect();
+    let mut files = HashSet::new();
+    let mut sparse_dirs = Vec::new();
+    for path in paths.into_iter().filter(|path| !path.is_empty()) {
+        let full = worktree.join(OsStr::from_bytes(&path));
+        if path.ends_with(b"/") {
+            sparse_dirs.push(full);
+        } else {
+            files.insert(full);
+        }
+    }
+    Ok((files, sparse_dirs))
+}
 
-    let total = all_files.len();
+// 嵌套的仓库 (子模块等) 单独报告，不计入外层仓库
+fn is_nested_repo(dir: &Path) -> bool {
+    dir.join(".git").symlink_metadata().is_ok()
+}
 
-    // 并行处理,找出旧文件
-    let items: Vec<DiskItem> = all_files
-        .par_iter()
-        .filter_map(|entry| {
-            let entry_path = entry.path();
-            let metadata = entry_path.metadata().ok()?;
+fn analyze_git_repo(worktree: &Path, seen_inodes: &InodeSet) -> GitRepoReport {
+    use ignore::WalkBuilder;
+    use walkdir::WalkDir;
 
-            // 检查最后修改时间
-            let modified = metadata.modified().ok()?;
-            let age = now.duration_since(modified).ok()?;
+    let mut report = GitRepoReport {
+        name: worktree
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        path: worktree.to_string_lossy().to_string(),
+        ..Default::default()
+    };
 
-            // 过滤未超过时间阈值的文件
-            if age < threshold_duration {
-                return None;
+    // .git 存储占用
+    if let Some(git_dir) = resolve_git_dir(worktree) {
+        report.git_dir = git_dir.to_string_lossy().to_string();
+        for entry in WalkDir::new(&git_dir)
+            .follow_links(false)
+            .into_iter()
+            // 子模块 (modules/) 和附加工作树 (worktrees/) 的 git 目录由它们自己的仓库统计
+            .filter_entry(|e| {
+                e.depth() != 1 || !matches!(e.file_name().to_str(), Some("modules" | "worktrees"))
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| !e.file_type().is_dir())
+        {
+            let metadata = match entry.metadata() {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            if is_duplicate_inode(metadata.dev(), metadata.ino(), seen_inodes) {
+                continue; // 本地克隆会硬链接对象文件
             }
-
-            let name = entry_path.file_name()?.to_string_lossy().to_string();
             let size = metadata.blocks() * 512;
-            let file_type = get_file_type(&name);
+            let relative = entry.path().strip_prefix(&git_dir).unwrap_or(entry.path());
+            let bucket = if relative.starts_with("objects/pack") {
+                &mut report.storage.packs
+            } else if relative.starts_with("objects") {
+                &mut report.storage.loose_objects
+            } else if relative.starts_with("lfs") {
+                &mut report.storage.lfs
+            } else {
+                &mut report.storage.other
+            };
+            *bucket += size;
+            report.storage.total += size;
+        }
 
-            // 更新进度
-            let curr = current.fetch_add(1, Ordering::Relaxed) + 1;
-            if curr % 100 == 0 {
-                let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
-                let percent = ((curr as f64 / total as f64) * 100.0) as u8;
-                let _ = window.emit(
-                    "scan-progress",
-                    ScanProgress {
-                        percent,
-                        current: curr,
-                        total,
-                        current_item: name.clone(),
-                        elapsed_seconds: elapsed,
-                        estimated_remaining_seconds: 0,
-                    },
-                );
+        // 已跟踪文件来自索引
+        let (tracked, sparse_dirs) = match read_tracked_paths(worktree, &git_dir) {
+            Ok(tracked) => tracked,
+            Err(e) => {
+                report.error = Some(e);
+                (HashSet::new(), Vec::new())
             }
+        };
 
-            Some(DiskItem {
-                name,
-                path: entry_path.to_string_lossy().to_string(),
-                size,
-                is_directory: false,
-                item_count: 0,
-                file_type,
-                error: None,
+        // 不被忽略规则命中的文件 (ignore 会按 git 的规则读取各级 .gitignore 与 info/exclude)
+        let not_ignored: HashSet<PathBuf> = WalkBuilder::new(worktree)
+            .hidden(false)
+            .parents(false)
+            .ignore(false)
+            .git_ignore(true)
+            .git_exclude(true)
+            .git_global(true)
+            .follow_links(false)
+            .filter_entry(|e| {
+                e.file_name() != ".git" && (e.depth() == 0 || !is_nested_repo(e.path()))
             })
-        })
-        .collect();
+            .build()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| !t.is_dir()).unwrap_or(false))
+            .map(|e| e.into_path())
+            .collect();
 
-    // 按大小降序排序
-    let mut items = items;
-    items.sort_by(|a, b| b.size.cmp(&a.size));
+        // 完整遍历工作树并分类
+        for entry in WalkDir::new(worktree)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|e| {
+                e.file_name() != ".git" && (e.depth() == 0 || !is_nested_repo(e.path()))
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| !e.file_type().is_dir())
+        {
+            let size = match entry.metadata() {
+                Ok(m) => m.blocks() * 512,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if tracked.contains(path) || sparse_dirs.iter().any(|dir| path.starts_with(dir)) {
+                report.tracked_bytes += size;
+                report.tracked_files += 1;
+            } else if not_ignored.contains(path) {
+                report.untracked_bytes += size;
+                report.untracked_files += 1;
+            } else {
+                report.ignored_bytes += size;
+                report.ignored_files += 1;
+            }
+        }
+    }
 
-    Ok(ScanResult { items })
+    report.total_bytes =
+        report.storage.total + report.tracked_bytes + report.untracked_bytes + report.ignored_bytes;
+    report
 }
 
-// 重复文件扫描 (通过大小 + 部分哈希识别)
+// Git 仓库分析: 按仓库统计 .git 存储、已跟踪、未跟踪和被忽略的文件 (不依赖 git 命令)
 #[tauri::command]
-async fn scan_duplicate_files(path: String, window: Window) -> Result<ScanResult, String> {
+async fn scan_git_repos(path: String, window: Window) -> Result<Vec<GitRepoReport>, String> {
+    use rayon::prelude::*;
     use walkdir::WalkDir;
 
     init_rayon_pool();
 
     let path_obj = Path::new(&path);
-    let start_time = SystemTime::now();
+    if !path_obj.is_dir() {
+        return Err("目录不存在".to_string());
+    }
 
-    // 第一步: 按文件大小分组
-    let mut size_groups: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let start_time = SystemTime::now();
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 5,
+            current: 0,
+            total: 0,
+            current_item: "正在查找 Git 仓库...".to_string(),
+            elapsed_seconds: 0,
+            estimated_remaining_seconds: 0,
+        },
+    );
 
-    for entry in WalkDir::new(path_obj)
+    // 查找工作树根目录 (含 .git 目录或 .git 文件的目录)
+    let mut ignore_rules = IgnoreStack::default();
+    let repos: Vec<PathBuf> = WalkDir::new(path_obj)
         .follow_links(false)
         .into_iter()
+        .filter_entry(|e| e.file_name() != ".git" && ignore_rules.allows(e))
         .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-    {
-        if let Ok(metadata) = entry.metadata() {
-            let size = metadata.len();
-            if size > 0 {
-                // 跳过空文件
-                size_groups
-                    .entry(size)
-                    .or_insert_with(Vec::new)
-                    .push(entry.path().to_path_buf());
-            }
-        }
-    }
-
-    // 第二步: 对每个大小组,计算部分哈希 (前 1KB + 后 1KB)
-    let duplicate_candidates: Vec<_> = size_groups
-        .into_iter()
-        .filter(|(_, paths)| paths.len() > 1) // 只保留有多个文件的组
+        .filter(|e| e.file_type().is_dir() && is_nested_repo(e.path()))
+        .map(|e| e.into_path())
         .collect();
 
-    let total = duplicate_candidates.len();
-    let current = Arc::new(AtomicUsize::new(0));
-
-    let mut all_duplicates: Vec<DiskItem> = Vec::new();
-
-    for (size, paths) in duplicate_candidates {
-        // 计算每个文件的部分哈希
-        let mut hash_groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
-
-        for path in paths {
-            if let Ok(hash) = calculate_partial_hash(&path) {
-                hash_groups
-                    .entry(hash)
-                    .or_insert_with(Vec::new)
-                    .push(path);
-            }
-        }
-
-        // 找出有重复哈希的文件
-        for (_hash, dup_paths) in hash_groups {
-            if dup_paths.len() > 1 {
-                // 找到重复文件!
-                for dup_path in dup_paths {
-                    let name = dup_path
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string();
-                    let file_type = get_file_type(&name);
-
-                    all_duplicates.push(DiskItem {
-                        name,
-                        path: dup_path.to_string_lossy().to_string(),
-                        size,
-                        is_directory: false,
-                        item_count: 0,
-                        file_type,
-                        error: None,
-                    });
-                }
-            }
-        }
+    let total = repos.len();
+    let processed = AtomicUsize::new(0);
+    let seen_inodes: InodeSet = Arc::new(Mutex::new(HashSet::new()));
+    let mut reports: Vec<GitRepoReport> = repos
+        .par_iter()
+        .map(|repo| {
+            let report = analyze_git_repo(repo, &seen_inodes);
 
-        // 更新进度
-        let curr = current.fetch_add(1, Ordering::Relaxed) + 1;
-        if curr % 10 == 0 {
+            let curr = processed.fetch_add(1, Ordering::Relaxed) + 1;
             let elapsed = start_time.elapsed().unwrap_or_default().as_secs();
-            let percent = ((curr as f64 / total as f64) * 100.0) as u8;
+            let speed = if elapsed > 0 {
+                curr as f64 / elapsed as f64
+            } else {
+                0.0
+            };
+            let remaining = if speed > 0.0 && total > curr {
+                ((total - curr) as f64 / speed) as u64
+            } else {
+                0
+            };
             let _ = window.emit(
                 "scan-progress",
                 ScanProgress {
-                    percent,
+                    percent: (10 + (curr * 85 / total.max(1))) as u8,
                     current: curr,
                     total,
-                    current_item: format!("检测大小: {} 字节", size),
+                    current_item: report.name.clone(),
                     elapsed_seconds: elapsed,
-                    estimated_remaining_seconds: 0,
+                    estimated_remaining_seconds: remaining,
                 },
             );
-        }
-    }
-
-    // 按大小降序排序
-    all_duplicates.sort_by(|a, b| b.size.cmp(&a.size));
-
-    Ok(ScanResult {
-        items: all_duplicates,
-    })
-}
-
-// 计算部分哈希 (前 1KB + 后 1KB)
-fn calculate_partial_hash(path: &Path) -> Result<String, std::io::Error> {
-    use std::io::{Read, Seek, SeekFrom};
-
-    let mut file = fs::File::open(path)?;
-    let metadata = file.metadata()?;
-    let file_size = metadata.len();
 
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    use std::hash::Hasher;
+            report
+        })
+        .collect();
 
-    // 读取前 1KB
-    let mut buffer = vec![0u8; 1024.min(file_size as usize)];
-    file.read_exact(&mut buffer)?;
-    hasher.write(&buffer);
+    reports.sort_by_key(|r| Reverse(r.total_bytes));
 
-    // 如果文件大于 2KB,读取后 1KB
-    if file_size > 2048 {
-        file.seek(SeekFrom::End(-1024))?;
-        buffer.resize(1024, 0);
-        file.read_exact(&mut buffer)?;
-        hasher.write(&buffer);
-    }
+    let _ = window.emit(
+        "scan-progress",
+        ScanProgress {
+            percent: 100,
+            current: total,
+            total,
+            current_item: "完成".to_string(),
+            elapsed_seconds: start_time.elapsed().unwrap_or_default().as_secs(),
+            estimated_remaining_seconds: 0,
+        },
+    );
 
-    Ok(format!("{:x}", hasher.finish()))
+    Ok(reports)
 }
 
 // ==================== 主函数 ====================
@@ -843,8 +5080,549 @@ fn main() {
             get_home_dir,
             scan_large_files,
             scan_old_files,
-            scan_duplicate_files
+            scan_empty_items,
+            scan_broken_symlinks,
+            scan_build_artifacts,
+            scan_tool_caches,
+            scan_git_repos,
+            scan_duplicate_files,
+            scan_duplicate_files_multi,
+            get_file_types,
+            reload_file_types,
+            select_duplicate_keepers,
+            dedupe_group,
+            scan_duplicate_directories,
+            scan_similar_images,
+            clear_hash_cache
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 命令中没有真正的异步等待，轮询到完成即可
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cleandir-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dedupe_group_rolls_back_on_content_mismatch() {
+        let dir = temp_dir("dedupe");
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        for (name, content) in [
+            ("keep", "same data"),
+            ("first", "same data"),
+            ("second", "diff data"),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, content).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let first_ino = fs::metadata(dir.join("first")).unwrap().ino();
+
+        let result = block_on(dedupe_group(
+            path("keep"),
+            vec![path("first"), path("second")],
+            Some(DedupeMethod::Hardlink),
+        ));
+        assert!(result.is_err());
+
+        // 第一个副本已被替换过，必须恢复为原来的 inode 和内容
+        let first = fs::metadata(dir.join("first")).unwrap();
+        assert_eq!(first.ino(), first_ino);
+        assert_eq!(fs::read_to_string(dir.join("first")).unwrap(), "same data");
+        assert_eq!(fs::read_to_string(dir.join("second")).unwrap(), "diff data");
+
+        // 不留下临时文件或备份
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["first", "keep", "second"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn image(name: &str, width: u32) -> SimilarImage {
+        SimilarImage {
+            name: name.to_string(),
+            path: name.to_string(),
+            size: 100,
+            width,
+            height: width,
+            hash: String::new(),
+            distance: 0,
+        }
+    }
+
+    #[test]
+    fn similar_images_are_not_chained_transitively() {
+        // a~b、b~c 都在阈值内，但 a 与 c 相距 8 位，不能归入同一簇
+        let images = vec![
+            (image("a", 300), 0x00u64),
+            (image("b", 200), 0x0fu64),
+            (image("c", 100), 0xffu64),
+        ];
+        let clusters = split_by_keeper(images, 5);
+        assert_eq!(clusters.len(), 1);
+        let names: Vec<&str> = clusters[0].images.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(clusters[0].images[1].distance, 4);
+        assert_eq!(clusters[0].reclaimable_bytes, 100);
+    }
+
+    fn scan_duplicates(roots: &[(&Path, bool)]) -> DuplicateScanResult {
+        let roots: Vec<DuplicateRoot> = roots
+            .iter()
+            .map(|(path, reference)| DuplicateRoot {
+                path: path.to_string_lossy().to_string(),
+                reference: *reference,
+            })
+            .collect();
+        run_duplicate_scan(&roots, None, false, DEFAULT_IO_THREADS_PER_DEVICE).unwrap()
+    }
+
+    #[test]
+    fn duplicates_are_verified_by_full_content() {
+        let dir = temp_dir("dup-middle");
+        // 大小、开头 4KB 和结尾 4KB 都相同，只有中间不同
+        let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+        let mut middle = content.clone();
+        middle[10_000] ^= 1;
+        fs::write(dir.join("a"), &content).unwrap();
+        fs::write(dir.join("b"), &content).unwrap();
+        fs::write(dir.join("c"), &middle).unwrap();
+
+        let result = scan_duplicates(&[(&dir, false)]);
+        assert_eq!(result.groups.len(), 1);
+        let names: Vec<&str> = result.groups[0]
+            .files
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(!names.contains(&"c"));
+        assert_eq!(result.total_reclaimable_bytes, 20_000);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn duplicate_groups_are_sorted_by_reclaimable_space() {
+        let dir = temp_dir("dup-groups");
+        for name in ["x1.txt", "x2.txt", "x3.txt"] {
+            fs::write(dir.join(name), "small").unwrap();
+        }
+        for name in ["y1.mp4", "y2.mp4"] {
+            fs::write(dir.join(name), "a larger duplicate").unwrap();
+        }
+        fs::write(dir.join("empty1"), "").unwrap();
+        fs::write(dir.join("empty2"), "").unwrap();
+
+        // 空文件不算重复；每组只保留一份时可释放 size × (副本数 - 1)
+        let result = scan_duplicates(&[(&dir, false)]);
+        let summary: Vec<(u64, usize, u64, Option<&str>)> = result
+            .groups
+            .iter()
+            .map(|g| {
+                (
+                    g.size,
+                    g.files.len(),
+                    g.reclaimable_bytes,
+                    g.file_type.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [(18, 2, 18, Some("video")), (5, 3, 10, Some("document"))]
+        );
+        assert_eq!(result.total_reclaimable_bytes, 28);
+        assert_eq!(
+            result.groups[0].hash,
+            blake3::hash(b"a larger duplicate").to_hex().to_string()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hard_links_fold_into_one_member() {
+        let dir = temp_dir("dup-links");
+        fs::write(dir.join("a"), "content").unwrap();
+        fs::hard_link(dir.join("a"), dir.join("a2")).unwrap();
+        fs::write(dir.join("b"), "content").unwrap();
+        // 只有硬链接、没有其他副本的 inode 不算重复
+        fs::write(dir.join("lonely"), "another").unwrap();
+        fs::hard_link(dir.join("lonely"), dir.join("lonely2")).unwrap();
+
+        let result = scan_duplicates(&[(&dir, false)]);
+        assert_eq!(result.groups.len(), 1);
+        let group = &result.groups[0];
+        assert_eq!(group.files.len(), 2);
+        let linked = group.files.iter().find(|f| f.name == "a").unwrap();
+        assert_eq!(linked.hard_links, [dir.join("a2").to_string_lossy()]);
+        assert!(linked.already_linked);
+        // 硬链接不占额外空间，只有 b 可释放
+        assert_eq!(group.reclaimable_bytes, 7);
+        assert_eq!(result.total_reclaimable_bytes, 7);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reference_roots_only_report_candidates_with_a_reference_copy() {
+        let dir = temp_dir("dup-reference");
+        let reference = dir.join("reference");
+        let candidates = dir.join("candidates");
+        fs::create_dir_all(&reference).unwrap();
+        fs::create_dir_all(&candidates).unwrap();
+        fs::write(reference.join("shared"), "shared").unwrap();
+        fs::write(candidates.join("shared1"), "shared").unwrap();
+        fs::write(candidates.join("shared2"), "shared").unwrap();
+        // 只在参考目录内重复、只在候选目录内重复的组都不报告
+        fs::write(reference.join("ref1"), "ref only").unwrap();
+        fs::write(reference.join("ref2"), "ref only").unwrap();
+        fs::write(candidates.join("new1"), "new only").unwrap();
+        fs::write(candidates.join("new2"), "new only").unwrap();
+
+        let result = scan_duplicates(&[(&reference, true), (&candidates, false)]);
+        assert_eq!(result.groups.len(), 1);
+        let group = &result.groups[0];
+        let mut members: Vec<(&str, bool)> = group
+            .files
+            .iter()
+            .map(|f| (f.name.as_str(), f.is_reference))
+            .collect();
+        members.sort();
+        assert_eq!(
+            members,
+            [("shared", true), ("shared1", false), ("shared2", false)]
+        );
+        // 只计算候选文件，参考副本不算可释放空间
+        assert_eq!(group.reclaimable_bytes, 12);
+        assert_eq!(result.total_reclaimable_bytes, 12);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn duplicate_file(path: &str, modified: u64) -> DuplicateFile {
+        DuplicateFile {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path: path.to_string(),
+            modified: Some(modified),
+            owner: None,
+            hard_links: Vec::new(),
+            already_linked: false,
+            is_reference: false,
+        }
+    }
+
+    #[test]
+    fn protected_copies_are_the_kept_set() {
+        let group = DuplicateGroup {
+            hash: "h".to_string(),
+            size: 10,
+            file_type: None,
+            files: vec![
+                duplicate_file("/a/old", 1),
+                duplicate_file("/safe/mid", 2),
+                duplicate_file("/safe/new", 3),
+                duplicate_file("/b/newest", 4),
+            ],
+            reclaimable_bytes: 30,
+        };
+        let policy = KeeperPolicy {
+            strategy: KeepStrategy::Oldest,
+            preferred_dir: None,
+            protected_dirs: vec!["/safe".to_string()],
+        };
+        let selection = select_duplicate_keepers(vec![group], policy).unwrap();
+        let group = &selection.selections[0];
+        // 策略只在受保护副本中挑选，未受保护的副本全部删除
+        assert_eq!(group.keep, "/safe/mid");
+        assert_eq!(group.protected, ["/safe/new"]);
+        assert_eq!(group.remove, ["/a/old", "/b/newest"]);
+        assert_eq!(selection.total_freed_bytes, 20);
+    }
+
+    #[test]
+    fn cleandirignore_rules_apply_only_inside_the_walk() {
+        let dir = temp_dir("ignore");
+        fs::create_dir_all(dir.join("repo/keep")).unwrap();
+        fs::create_dir_all(dir.join("repo/fixtures")).unwrap();
+        fs::create_dir_all(dir.join("repo/tmp")).unwrap();
+        fs::create_dir_all(dir.join("proj/data")).unwrap();
+        fs::create_dir_all(dir.join("proj/src")).unwrap();
+        fs::write(
+            dir.join("repo/.cleandirignore"),
+            "keep/\n[skip]\nfixtures/\n",
+        )
+        .unwrap();
+        // 扫描根目录之外的规则不生效
+        fs::write(dir.join(".cleandirignore"), "tmp/\nproj/data/\n").unwrap();
+
+        let walk = |count_protected: bool| {
+            let mut ignore_rules = IgnoreStack::default();
+            let mut names: Vec<String> = walkdir::WalkDir::new(dir.join("repo"))
+                .min_depth(1)
+                .into_iter()
+                .filter_entry(|e| {
+                    if count_protected {
+                        ignore_rules.allows_stats(e)
+                    } else {
+                        ignore_rules.allows(e)
+                    }
+                })
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(walk(false), [".cleandirignore", "tmp"]);
+        assert_eq!(walk(true), [".cleandirignore", "keep", "tmp"]);
+
+        assert!(deletion_blocked(&dir.join("repo/keep")).is_some());
+        assert!(deletion_blocked(&dir.join("repo")).is_some());
+        // 上层目录的规则保护的是子目录时，删除外层目录同样被拒绝
+        assert!(deletion_blocked(&dir.join("proj/data")).is_some());
+        assert!(deletion_blocked(&dir.join("proj")).is_some());
+        assert!(deletion_blocked(&dir.join("proj/src")).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 手工构造 SHA-1 仓库的索引文件: entries 为 (路径, 是否带扩展标志位)
+    #[test]
+    fn nested_roots_merge_into_their_ancestor() {
+        let dir = temp_dir("overlapping-roots");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::create_dir_all(dir.join("c")).unwrap();
+        fs::write(dir.join("file"), "").unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let root = |name: &str| fs::canonicalize(dir.join(name)).unwrap();
+
+        let roots =
+            resolve_overlapping_roots(&[path("a/b"), path("c"), path("a"), path("a/")]).unwrap();
+        assert_eq!(
+            roots,
+            [
+                (root("c"), vec![]),
+                (root("a"), vec![path("a/"), path("a/b")]),
+            ]
+        );
+
+        // 同名前缀不算嵌套
+        fs::create_dir_all(dir.join("ab")).unwrap();
+        let roots = resolve_overlapping_roots(&[path("a"), path("ab")]).unwrap();
+        assert_eq!(roots.len(), 2);
+
+        assert!(resolve_overlapping_roots(&[path("file")]).is_err());
+        assert!(resolve_overlapping_roots(&[path("missing")]).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn index_blob(version: u32, entries: &[(&[u8], bool)], extensions: &[u8]) -> Vec<u8> {
+        let mut data = b"DIRC".to_vec();
+        data.extend(version.to_be_bytes());
+        data.extend((entries.len() as u32).to_be_bytes());
+        let mut previous: &[u8] = b"";
+        for &(path, extended) in entries {
+            let start = data.len();
+            data.extend([0u8; 40]);
+            data.extend([0xab; 20]);
+            let flags = path.len() as u16 | if extended { 0x4000 } else { 0 };
+            data.extend(flags.to_be_bytes());
+            if extended {
+                data.extend(0x2000u16.to_be_bytes()); // intent-to-add
+            }
+            if version == 4 {
+                let common = previous
+                    .iter()
+                    .zip(path)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                data.push((previous.len() - common) as u8);
+                data.extend(&path[common..]);
+                data.push(0);
+            } else {
+                data.extend(path);
+                let len = data.len() - start;
+                data.extend(vec![0; 8 - len % 8]);
+            }
+            previous = path;
+        }
+        data.extend(extensions);
+        data.extend([0u8; 20]);
+        data
+    }
+
+    #[test]
+    fn index_varint_matches_git_encoding() {
+        assert_eq!(read_index_varint(&[0x05]), Some((5, 1)));
+        assert_eq!(read_index_varint(&[0x80, 0x00]), Some((128, 2)));
+        assert_eq!(read_index_varint(&[0x81, 0x7f, 0xff]), Some((383, 2)));
+        assert_eq!(read_index_varint(&[0x80]), None);
+        assert_eq!(read_index_varint(&[]), None);
+    }
+
+    #[test]
+    fn git_index_entries_are_parsed_for_each_version() {
+        let entries: &[(&[u8], bool)] = &[
+            (b"src/lib.rs", false),
+            (b"src/main.rs", true),
+            (b"README.md", false),
+        ];
+        let expected: Vec<Vec<u8>> = entries.iter().map(|(path, _)| path.to_vec()).collect();
+
+        let v3 = parse_git_index(&index_blob(3, entries, &[]), 20).unwrap();
+        assert_eq!(v3.paths, expected);
+        assert!(v3.link.is_none());
+
+        // v4 中 src/main.rs 只存储 "main.rs" 并去掉上一条末尾的 6 字节
+        let v4 = index_blob(4, entries, &[]);
+        assert!(v4.windows(9).any(|w| w == b"\x06main.rs\0"));
+        assert_eq!(parse_git_index(&v4, 20).unwrap().paths, expected);
+
+        let mut truncated = index_blob(2, &entries[..1], &[]);
+        truncated.truncate(40);
+        assert!(parse_git_index(&truncated, 20).is_err());
+
+        // 伪造的超大条目数只会报错，不会按它预分配内存
+        let mut forged = index_blob(2, &entries[..1], &[]);
+        forged[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse_git_index(&forged, 20).is_err());
+    }
+
+    #[test]
+    fn ewah_bitmap_is_bounded_by_its_bit_size() {
+        let bitmap = |bit_size: u32, words: &[u64]| {
+            let mut data = bit_size.to_be_bytes().to_vec();
+            data.extend((words.len() as u32).to_be_bytes());
+            for word in words {
+                data.extend(word.to_be_bytes());
+            }
+            data
+        };
+        // 两个全 1 的连续字后跟一个字面字
+        let words = [(2 << 1) | 1 | (1 << 33), 0b101];
+        let bits = read_ewah_bitmap(&bitmap(131, &words)).unwrap();
+        assert_eq!(bits, (0..128).chain([128, 130]).collect::<Vec<_>>());
+        // 位数只有 3 时，超出部分被截掉
+        assert_eq!(read_ewah_bitmap(&bitmap(3, &words)).unwrap(), [0, 1, 2]);
+        // 声称 2^32-1 个全 1 连续字也不会展开
+        let huge = [(0xffff_ffffu64 << 1) | 1];
+        assert_eq!(read_ewah_bitmap(&bitmap(2, &huge)).unwrap(), [0, 1]);
+    }
+
+    #[test]
+    fn split_index_merges_shared_entries() {
+        let dir = temp_dir("split-index");
+        let git_dir = dir.join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        let shared_hash = [0x11u8; 20];
+        fs::write(
+            git_dir.join(format!("sharedindex.{}", "11".repeat(20))),
+            index_blob(2, &[(b"a", false), (b"b", false), (b"c", false)], &[]),
+        )
+        .unwrap();
+
+        // 删除位图只置位第 1 项 (b)，替换位图为空
+        let mut link = shared_hash.to_vec();
+        for words in [&[1u64 << 33, 0b10][..], &[][..]] {
+            link.extend(3u32.to_be_bytes());
+            link.extend((words.len() as u32).to_be_bytes());
+            for word in words {
+                link.extend(word.to_be_bytes());
+            }
+            link.extend(0u32.to_be_bytes());
+        }
+        let mut extension = b"link".to_vec();
+        extension.extend((link.len() as u32).to_be_bytes());
+        extension.extend(link);
+        fs::write(
+            git_dir.join("index"),
+            index_blob(2, &[(b"", false), (b"d", false)], &extension),
+        )
+        .unwrap();
+
+        let (files, _) = read_tracked_paths(&dir, &git_dir).unwrap();
+        let mut names: Vec<_> = files
+            .iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        names.sort();
+        assert_eq!(names, [Path::new("a"), Path::new("c"), Path::new("d")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hash_cache_journal_round_trips() {
+        let dir = temp_dir("hash-cache");
+        let file = dir.join(HASH_CACHE_FILE);
+        let identity = |ino| FileIdentity {
+            dev: 1,
+            ino,
+            size: 10,
+            mtime: 0,
+            mtime_nsec: 0,
+            ctime: 0,
+            ctime_nsec: 0,
+        };
+
+        let cache = HashCache::load_from(Some(file.clone()));
+        cache.put_full(identity(1), "old".to_string());
+        cache.put_partial(identity(3), "partial".to_string());
+        cache.save().unwrap();
+        cache.put_full(identity(1), "new".to_string());
+        cache.put_full(identity(2), "two".to_string());
+        cache.save().unwrap();
+        // 模拟写到一半被打断的最后一行
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .write_all(b"[{\"dev\":1,\"ino\"")
+            .unwrap();
+
+        let cache = HashCache::load_from(Some(file.clone()));
+        assert_eq!(cache.get_full(&identity(1)).as_deref(), Some("new"));
+        assert_eq!(cache.get_full(&identity(2)).as_deref(), Some("two"));
+        assert_eq!(cache.get_partial(&identity(3)), None);
+
+        // 追加的新记录不会和残缺的行粘在一起
+        cache.put_full(identity(4), "four".to_string());
+        cache.save().unwrap();
+        let cache = HashCache::load_from(Some(file));
+        assert_eq!(cache.get_full(&identity(4)).as_deref(), Some("four"));
+        assert_eq!(cache.get_full(&identity(1)).as_deref(), Some("new"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}